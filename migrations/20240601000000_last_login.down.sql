@@ -0,0 +1,2 @@
+-- Add down migration script here
+ALTER TABLE bw_account DROP COLUMN IF EXISTS last_login_at;
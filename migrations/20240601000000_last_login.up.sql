@@ -0,0 +1,4 @@
+-- Add up migration script here
+ALTER TABLE bw_account ADD COLUMN last_login_at TIMESTAMP DEFAULT NULL;
+
+COMMENT ON COLUMN bw_account.last_login_at IS '最近登录时间';
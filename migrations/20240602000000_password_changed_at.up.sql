@@ -0,0 +1,4 @@
+-- Add up migration script here
+ALTER TABLE bw_account ADD COLUMN password_changed_at TIMESTAMP DEFAULT NULL;
+
+COMMENT ON COLUMN bw_account.password_changed_at IS '最近修改密码时间(UTC)';
@@ -0,0 +1,3 @@
+-- Add down migration script here
+DROP INDEX IF EXISTS idx_bw_account_email_lower;
+ALTER TABLE bw_account ADD CONSTRAINT bw_account_email_key UNIQUE (email);
@@ -0,0 +1,8 @@
+-- Add up migration script here
+-- Emails are unique regardless of case. Rows stored before emails were
+-- normalized are folded first, case variants of one address have to be
+-- merged by hand before this can run.
+UPDATE bw_account SET email = lower(trim(email)) WHERE email <> lower(trim(email));
+
+ALTER TABLE bw_account DROP CONSTRAINT IF EXISTS bw_account_email_key;
+CREATE UNIQUE INDEX idx_bw_account_email_lower ON bw_account (lower(email));
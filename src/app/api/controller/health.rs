@@ -0,0 +1,83 @@
+use std::sync::Arc;
+
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+
+use crate::{
+    app::{
+        bootstrap::AppState,
+        entity::health::{DependencyStatus, ReadinessResponse},
+    },
+    library::cfg,
+};
+
+/// Liveness: answers as long as the server runs.
+#[allow(clippy::unused_async)]
+pub async fn health_handler() -> impl IntoResponse {
+    Json(serde_json::json!({ "status": DependencyStatus::Up }))
+}
+
+/// Readiness: up when all of [`check_dependencies`] are.
+pub async fn ready_handler(
+    State(state): State<Arc<AppState>>,
+) -> impl IntoResponse {
+    readiness(&check_dependencies(&state).await)
+}
+
+/// Checks the database, Redis and the MQ concurrently, each within its
+/// `app.health` timeout.
+pub async fn check_dependencies(state: &AppState) -> [(&'static str, bool); 3] {
+    let health = &cfg::config().app.health;
+    let mq = &state.services.message_queue.mqer;
+    let (db, redis, mq) = tokio::join!(
+        state.db.healthy(health.db_timeout()),
+        state.redis.healthy(health.redis_timeout()),
+        mq.healthy(health.mq_timeout()),
+    );
+    [("database", db), ("redis", redis), ("mq", mq)]
+}
+
+fn readiness(
+    checks: &[(&'static str, bool)],
+) -> (StatusCode, Json<ReadinessResponse>) {
+    let response = ReadinessResponse::new(checks);
+    let status = match response.status {
+        DependencyStatus::Up => StatusCode::OK,
+        DependencyStatus::Down => StatusCode::SERVICE_UNAVAILABLE,
+    };
+    (status, Json(response))
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_ready_when_all_healthy() {
+        let (status, Json(response)) =
+            readiness(&[("database", true), ("redis", true), ("mq", true)]);
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(
+            serde_json::to_value(response).unwrap(),
+            json!({
+                "status": "up",
+                "checks": {"database": "up", "redis": "up", "mq": "up"},
+            })
+        );
+    }
+
+    #[test]
+    fn test_unavailable_when_one_dependency_down() {
+        let (status, Json(response)) =
+            readiness(&[("database", true), ("redis", false), ("mq", true)]);
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(
+            serde_json::to_value(response).unwrap(),
+            json!({
+                "status": "down",
+                "checks": {"database": "up", "redis": "down", "mq": "up"},
+            })
+        );
+    }
+}
@@ -0,0 +1,64 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::State, http::header::CONTENT_TYPE, response::IntoResponse,
+};
+
+use crate::{
+    app::{
+        api::middleware::metrics::{self, render_gauge},
+        bootstrap::AppState,
+    },
+    library::dber::PoolStatus,
+};
+
+/// Prometheus scrape target, in OpenMetrics text format.
+#[allow(clippy::unused_async)]
+pub async fn metrics_handler(
+    State(state): State<Arc<AppState>>,
+) -> impl IntoResponse {
+    let mut out = String::new();
+    metrics::metrics().render(&mut out);
+
+    let mq = &state.services.message_queue.mqer;
+    render_gauge(
+        &mut out,
+        "mq_in_flight",
+        "Publishes and deliveries being processed.",
+        &[("", mq.in_flight() as u64)],
+    );
+
+    let pools = state
+        .db
+        .pool_status()
+        .into_iter()
+        .map(|(name, status)| (format!("pool=\"{name}\""), status))
+        .collect::<Vec<_>>();
+    let gauge = |value: fn(&PoolStatus) -> u64| {
+        pools
+            .iter()
+            .map(|(labels, status)| (labels.as_str(), value(status)))
+            .collect::<Vec<_>>()
+    };
+    render_gauge(
+        &mut out,
+        "db_pool_connections",
+        "Open connections.",
+        &gauge(|status| u64::from(status.size)),
+    );
+    render_gauge(
+        &mut out,
+        "db_pool_idle_connections",
+        "Open connections not in use.",
+        &gauge(|status| status.idle as u64),
+    );
+    render_gauge(
+        &mut out,
+        "db_pool_max_connections",
+        "Connections the pool may open.",
+        &gauge(|status| u64::from(status.max_connections)),
+    );
+    out.push_str("# EOF\n");
+
+    ([(CONTENT_TYPE, metrics::CONTENT_TYPE)], out)
+}
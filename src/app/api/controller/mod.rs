@@ -1,2 +1,4 @@
 pub mod common;
+pub mod health;
+pub mod metrics;
 pub mod v1;
@@ -1,24 +1,34 @@
-use std::sync::Arc;
+use std::{future::Future, sync::Arc, time::Duration};
 
-use axum::{extract::State, response::IntoResponse, Json};
+use axum::{
+    extract::{Query, State},
+    response::IntoResponse,
+    Json,
+};
+use sqlx::PgPool;
 
 use crate::{
     app::{
-        bootstrap::{
-            constants::{self, MQ_SEND_EMAIL_QUEUE},
-            AppState,
-        },
+        api::extractor::{JsonOrForm, ValidatedJson},
+        bootstrap::{constants, AppState},
         entity::{
             account::{
-                ActiveAccountRequest, LoginResponse, LoginUserRequest,
+                ActivateByLinkRequest, ActivationResponse,
+                ActiveAccountRequest, EmailAvailableQuery,
+                EmailAvailableResponse, LoginResponse, LoginUserRequest,
                 RegisterUserRequest, ResetPasswordRequest, TokenResponse,
-                UserResponse,
+                UpdateProfileRequest, UserResponse,
             },
-            common::SuccessResponse,
+            common::{FieldsQuery, SuccessResponse},
+            event::UserRegistered,
+        },
+        service::{
+            email_template::{self, CodeEmail},
+            jwt_service::{Claims, RefreshTokenRequest, TokenType},
         },
-        service::jwt_service::{Claims, RefreshTokenRequest},
     },
     library::{
+        cfg::{self, CodeConfig},
         crypto,
         error::{
             ApiInnerError,
@@ -26,50 +36,108 @@ use crate::{
             AppResult, AuthInnerError,
         },
         mailor::Email,
+        validation, Redis,
     },
     models::{
         account::{Account, RegisterSchema, ResetPasswordSchema},
-        types::AccountStatus,
+        types::{AccountId, AccountStatus},
     },
 };
 
 pub async fn register_user_handler(
     State(state): State<Arc<AppState>>,
-    Json(body): Json<RegisterUserRequest>,
+    JsonOrForm(body): JsonOrForm<RegisterUserRequest>,
 ) -> AppResult<impl IntoResponse> {
-    if Account::check_user_exists_by_email(state.get_db(), &body.email)
-        .await?
-        .unwrap_or(true)
-    {
-        return Err(AuthError(AuthInnerError::UserAlreadyExists));
+    validation::validate_password_strength(&body.password)?;
+    ensure_name_available(state.get_read_db(), &body.name).await?;
+
+    if let Some(max_accounts) = cfg::config().app.max_accounts {
+        let count = Account::count_accounts(state.get_read_db()).await?;
+        check_account_quota(count, max_accounts)?;
     }
 
     let hashed_password = crypto::hash_password(body.password.as_bytes())?;
     let item = RegisterSchema {
         name: body.name,
-        email: body.email,
+        email: validation::normalize_email(&body.email),
         password: hashed_password,
     };
 
-    let user = Account::register_account(state.get_db(), &item).await?;
+    let user = register(state.get_write_db(), &item).await?;
+
+    if let Err(e) = state.publish_event(&UserRegistered::from(&user)).await {
+        tracing::error!("Failed to publish user.registered event: {e}");
+    }
+
+    Ok(SuccessResponse {
+        msg: "success",
+        data: Some(Json(UserResponse::from(user))),
+    })
+}
+
+/// Rejects registrations whose name is already taken, as it's used to log
+/// in like the email.
+async fn ensure_name_available(db: &PgPool, name: &str) -> AppResult<()> {
+    if Account::check_user_exists_by_name(db, name)
+        .await?
+        .unwrap_or(true)
+    {
+        return Err(AuthError(AuthInnerError::NameAlreadyTaken));
+    }
+    Ok(())
+}
+
+/// Creates the account, rejecting a taken email. Left to the unique
+/// constraint rather than checked beforehand, which would race with
+/// concurrent registrations.
+async fn register(db: &PgPool, item: &RegisterSchema) -> AppResult<Account> {
+    Account::register_account(db, item)
+        .await?
+        .ok_or(AuthError(AuthInnerError::UserAlreadyExists))
+}
+
+pub async fn email_available_handler(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<EmailAvailableQuery>,
+) -> AppResult<impl IntoResponse> {
+    let available = email_available(state.get_read_db(), &query).await?;
 
     Ok(SuccessResponse {
         msg: "success",
-        data: Some(Json(UserResponse {
-            email: user.email,
-            language: user.language,
-            status: user.status,
-        })),
+        data: Some(Json(EmailAvailableResponse { available })),
     })
 }
 
+async fn email_available(
+    db: &PgPool,
+    query: &EmailAvailableQuery,
+) -> AppResult<bool> {
+    let exists = Account::check_user_exists_by_email(
+        db,
+        &validation::normalize_email(&query.email),
+    )
+    .await?
+    .unwrap_or(true);
+    Ok(!exists)
+}
+
+fn check_account_quota(count: i64, max_accounts: i64) -> AppResult<()> {
+    if count >= max_accounts {
+        return Err(ApiError(ApiInnerError::AccountQuotaExceeded));
+    }
+    Ok(())
+}
+
 pub async fn login_user_handler(
     State(state): State<Arc<AppState>>,
-    Json(body): Json<LoginUserRequest>,
+    JsonOrForm(body): JsonOrForm<LoginUserRequest>,
 ) -> AppResult<impl IntoResponse> {
+    crypto::reject_blank_password(&body.password)?;
+
     let users = Account::fetch_user_by_email_or_name(
-        state.get_db(),
+        state.get_read_db(),
         &body.email_or_name,
+        &validation::normalize_email(&body.email_or_name),
     )
     .await?;
     if users.is_empty() {
@@ -77,7 +145,14 @@ pub async fn login_user_handler(
     }
     for user in users {
         if crypto::verify_password(&user.password, &body.password)? {
-            let tokens = Claims::generate_tokens_for_user(&user).await?;
+            Account::touch_last_login(state.get_write_db(), user.id).await?;
+            let tokens = Claims::generate_tokens_for_user(
+                &user,
+                body.remember_me,
+                body.device_id.as_deref(),
+                &mut state.get_redis().await?,
+            )
+            .await?;
             return Ok(SuccessResponse {
                 msg: "Tokens generated successfully",
                 data: Some(Json(LoginResponse::new(tokens, user))),
@@ -91,33 +166,194 @@ pub async fn refresh_token_handler(
     State(state): State<Arc<AppState>>,
     Json(body): Json<RefreshTokenRequest>,
 ) -> AppResult<impl IntoResponse> {
-    let tokens = Claims::refresh_token(&body.refresh_token, state).await?;
+    let tokens = Claims::refresh_token(
+        &body.refresh_token,
+        body.device_id.as_deref(),
+        state,
+    )
+    .await?;
     Ok(SuccessResponse {
         msg: "Tokens refreshed successfully",
         data: Some(Json(TokenResponse { tokens })),
     })
 }
 
+/// Revokes the caller's access token and the given refresh token.
+pub async fn logout_handler(
+    State(state): State<Arc<AppState>>,
+    claims: Claims,
+    Json(body): Json<RefreshTokenRequest>,
+) -> AppResult<impl IntoResponse> {
+    let refresh =
+        Claims::parse_token(&body.refresh_token, TokenType::REFRESH, false)?;
+    if refresh.uid != claims.uid {
+        return Err(AuthError(AuthInnerError::InvalidToken));
+    }
+
+    let mut redis = state.get_redis().await?;
+    Claims::revoke(&claims.jti, claims.exp, &mut redis).await?;
+    Claims::revoke(&refresh.jti, refresh.exp, &mut redis).await?;
+
+    Ok(SuccessResponse {
+        msg: "Logged out successfully",
+        data: None::<()>,
+    })
+}
+
+/// Signs the caller out everywhere, invalidating every token issued so far.
+pub async fn invalidate_sessions_handler(
+    State(state): State<Arc<AppState>>,
+    claims: Claims,
+) -> AppResult<impl IntoResponse> {
+    Claims::invalidate_sessions(claims.uid, &mut state.get_redis().await?)
+        .await?;
+
+    Ok(SuccessResponse {
+        msg: "Sessions invalidated successfully",
+        data: None::<()>,
+    })
+}
+
+/// Soft-deletes the caller's account and invalidates all its sessions.
+pub async fn delete_me_handler(
+    State(state): State<Arc<AppState>>,
+    claims: Claims,
+) -> AppResult<impl IntoResponse> {
+    if !Account::soft_delete_by_uid(state.get_write_db(), claims.uid).await? {
+        return Err(AuthError(AuthInnerError::InvalidToken));
+    }
+    Claims::invalidate_sessions(claims.uid, &mut state.get_redis().await?)
+        .await?;
+
+    Ok(SuccessResponse {
+        msg: "Account deleted successfully",
+        data: None::<()>,
+    })
+}
+
+pub async fn update_profile_handler(
+    State(state): State<Arc<AppState>>,
+    claims: Claims,
+    ValidatedJson(body): ValidatedJson<UpdateProfileRequest>,
+) -> AppResult<impl IntoResponse> {
+    let mut cooldown = None;
+    if let Some(name) = &body.name {
+        let user = Account::fetch_user_by_uid(state.get_read_db(), claims.uid)
+            .await?
+            .ok_or(AuthError(AuthInnerError::InvalidToken))?;
+        if *name != user.name {
+            if Account::check_user_exists_by_name(state.get_read_db(), name)
+                .await?
+                .unwrap_or(true)
+            {
+                return Err(AuthError(AuthInnerError::NameAlreadyTaken));
+            }
+            let duration = cfg::config().app.name_change_cooldown;
+            if duration > 0 {
+                let key = format!(
+                    "{}:{}",
+                    constants::REDIS_NAME_CHANGE_KEY,
+                    claims.uid
+                );
+                cooldown = Some((key, Duration::from_secs(duration)));
+            }
+        }
+    }
+    let update = async {
+        let rows = Account::update_profile_by_uid(
+            state.get_write_db(),
+            claims.uid,
+            body.name,
+            body.language,
+        )
+        .await?;
+        if rows == 0 {
+            return Err(AuthError(AuthInnerError::InvalidToken));
+        }
+        Ok(())
+    };
+    match cooldown {
+        Some((key, duration)) => {
+            let mut redis = state.get_redis().await?;
+            with_cooldown(&mut redis, &key, duration, update).await?;
+        }
+        None => update.await?,
+    }
+
+    Ok(SuccessResponse {
+        msg: "Profile updated successfully",
+        data: None::<()>,
+    })
+}
+
 pub async fn get_me_handler(
     State(state): State<Arc<AppState>>,
     claims: Claims,
+    Query(fields): Query<FieldsQuery>,
 ) -> AppResult<impl IntoResponse> {
+    // The uid is stable, the email in the claims may be outdated.
     if let Some(user) =
-        Account::fetch_user_by_email(state.get_db(), &claims.email).await?
+        Account::fetch_user_by_uid(state.get_read_db(), claims.uid).await?
     {
+        let user = UserResponse::from(user);
         Ok(SuccessResponse {
             msg: "success",
-            data: Some(Json(UserResponse {
-                email: user.email,
-                language: user.language,
-                status: user.status,
-            })),
+            data: Some(Json(fields.select(&user, UserResponse::FIELDS)?)),
         })
     } else {
         Err(AuthError(AuthInnerError::InvalidToken))
     }
 }
 
+/// Starts a cooldown of `duration` under `key`, or asks to retry once the
+/// running one ends.
+async fn start_cooldown(
+    redis: &mut Redis,
+    key: &str,
+    duration: Duration,
+) -> AppResult<()> {
+    if !redis.set_nx_ex(key, 1, duration).await? {
+        let ttl = redis.ttl(key).await?.unwrap_or(duration);
+        return Err(ApiError(ApiInnerError::too_many_requests(ttl)));
+    }
+    Ok(())
+}
+
+/// Runs `action` once the cooldown at `key` is started, releasing it again
+/// when `action` fails so that the attempt isn't charged.
+async fn with_cooldown<T>(
+    redis: &mut Redis,
+    key: &str,
+    duration: Duration,
+    action: impl Future<Output = AppResult<T>>,
+) -> AppResult<T> {
+    start_cooldown(redis, key, duration).await?;
+    let result = action.await;
+    if result.is_err() {
+        if let Err(e) = redis.del(key).await {
+            tracing::error!("Failed to release the cooldown {key}: {e}");
+        }
+    }
+    result
+}
+
+/// Stores a fresh code under `key` for `cfg.validity`, unless one was
+/// already issued within `cfg.cooldown`, which is tracked separately so
+/// either can be the longer.
+async fn issue_code(
+    redis: &mut Redis,
+    key: &str,
+    cfg: CodeConfig,
+) -> AppResult<String> {
+    let cooldown = Duration::from_secs(cfg.cooldown.max(1));
+    start_cooldown(redis, &format!("{key}:cooldown"), cooldown).await?;
+    let code = crypto::random_code(crypto::CODE_LENGTH);
+    redis
+        .set_ex(key, &code, Duration::from_secs(cfg.validity.max(1)))
+        .await?;
+    Ok(code)
+}
+
 pub async fn send_active_account_email_handler(
     State(state): State<Arc<AppState>>,
     claims: Claims,
@@ -128,24 +364,29 @@ pub async fn send_active_account_email_handler(
         claims.uid,
         constants::REDIS_ACTIVE_ACCOUNT_KEY
     ));
-    if redis.get::<String>(&key).await?.is_some() {
-        return Err(ApiError(ApiInnerError::CodeIntervalRejection));
-    }
     if claims.status != AccountStatus::Inactive {
         return Err(AuthError(AuthInnerError::UserAlreadyActivated));
     }
-    let code = crypto::random_words(6);
-    let body = format!("Active Code: {}", code);
-
-    redis.set_ex(&key, &code, 60 * 5).await?;
+    let user = Account::fetch_user_by_uid(state.get_read_db(), claims.uid)
+        .await?
+        .ok_or(AuthError(AuthInnerError::InvalidToken))?;
+    let code = issue_code(&mut redis, &key, cfg::config().app.codes.activation)
+        .await?;
+    let (subject, mut body) =
+        CodeEmail::Activation.content(user.language, &code);
+    if let (Some(url), Some(token)) = (
+        cfg::config().app.activation_url.as_deref(),
+        Claims::generate_activation_token(&user)?,
+    ) {
+        body = email_template::with_activation_link(
+            user.language,
+            &body,
+            &activation_link(url, &token),
+        );
+    }
 
-    let email = Email::new(&claims.email, "Active your account", &body);
-    let email_json = serde_json::to_string(&email).map_err(|e| {
-        anyhow::anyhow!("Error occurred while sending email: {}", e)
-    })?;
     state
-        .get_mq()?
-        .basic_send(MQ_SEND_EMAIL_QUEUE, &email_json)
+        .enqueue_email(&Email::new(&user.email, subject, &body))
         .await?;
 
     Ok(SuccessResponse {
@@ -164,22 +405,17 @@ pub async fn send_reset_password_email_handler(
         claims.uid,
         constants::REDIS_RESET_PASSWORD_KEY
     ));
-    if redis.get::<String>(&key).await?.is_some() {
-        return Err(ApiError(ApiInnerError::CodeIntervalRejection));
-    }
-
-    let code = crypto::random_words(6);
-    let body = format!("ResetPassword Code: {}", code);
-
-    redis.set_ex(&key, &code, 60).await?;
+    let user = Account::fetch_user_by_uid(state.get_read_db(), claims.uid)
+        .await?
+        .ok_or(AuthError(AuthInnerError::InvalidToken))?;
+    let code =
+        issue_code(&mut redis, &key, cfg::config().app.codes.reset_password)
+            .await?;
+    let (subject, body) =
+        CodeEmail::ResetPassword.content(user.language, &code);
 
-    let email = Email::new(&claims.email, "Reset Password", &body);
-    let email_json = serde_json::to_string(&email).map_err(|e| {
-        anyhow::anyhow!("Error occurred while sending email: {}", e)
-    })?;
     state
-        .get_mq()?
-        .basic_send(MQ_SEND_EMAIL_QUEUE, &email_json)
+        .enqueue_email(&Email::new(&user.email, subject, &body))
         .await?;
 
     Ok(SuccessResponse {
@@ -193,35 +429,84 @@ pub async fn verify_active_account_code_handler(
     claims: Claims,
     Json(body): Json<ActiveAccountRequest>,
 ) -> AppResult<impl IntoResponse> {
-    let mut redis = state.get_redis().await?;
     if claims.status != AccountStatus::Inactive {
         return Err(AuthError(AuthInnerError::UserAlreadyActivated));
     }
-    let key = redis.key(&format!(
-        "{}:{}",
+    let activation = activate_account(
+        state.get_write_db(),
+        &mut state.get_redis().await?,
         claims.uid,
-        constants::REDIS_ACTIVE_ACCOUNT_KEY
-    ));
+        &validation::validate_code(&body.code)?,
+        claims.remember_me,
+    )
+    .await?;
 
-    if let Some(stored) = redis.get::<String>(&key).await? {
-        if stored == body.code {
-            redis.del(&key).await?;
-        } else {
-            return Err(AuthError(AuthInnerError::WrongCode));
-        }
-    }
+    Ok(SuccessResponse {
+        msg: "success",
+        data: Some(Json(activation)),
+    })
+}
 
-    let user = Account::fetch_user_by_uid(state.get_db(), claims.uid)
-        .await?
-        .ok_or(AuthError(AuthInnerError::WrongCredentials))?;
+/// Activates the account an emailed link was minted for. The link needs no
+/// login and works only once.
+pub async fn activate_by_link_handler(
+    State(state): State<Arc<AppState>>,
+    Json(body): Json<ActivateByLinkRequest>,
+) -> AppResult<impl IntoResponse> {
+    let mut redis = state.get_redis().await?;
+    let claims =
+        Claims::redeem_activation_token(&body.token, &mut redis).await?;
+    let activation =
+        activate(state.get_write_db(), &mut redis, claims.uid, false).await?;
 
-    let tokens = Claims::generate_tokens_for_user(&user).await?;
+    Ok(SuccessResponse {
+        msg: "success",
+        data: Some(Json(activation)),
+    })
+}
+
+/// `url` carrying `token` as its `token` query parameter.
+fn activation_link(url: &str, token: &str) -> String {
+    let separator = if url.contains('?') { '&' } else { '?' };
+    format!("{url}{separator}token={token}")
+}
 
+/// Activates `uid` once `code` matches the emailed one, returning tokens
+/// that carry the new status along with the updated user.
+async fn activate_account(
+    db: &PgPool,
+    redis: &mut Redis,
+    uid: AccountId,
+    code: &str,
+    remember_me: bool,
+) -> AppResult<ActivationResponse> {
+    let key =
+        redis.key(&format!("{}:{}", uid, constants::REDIS_ACTIVE_ACCOUNT_KEY));
+    if redis.get::<String>(&key).await?.as_deref() != Some(code) {
+        return Err(AuthError(AuthInnerError::WrongCode));
+    }
+
+    let activation = activate(db, redis, uid, remember_me).await?;
     redis.del(&key).await?;
+    Ok(activation)
+}
 
-    Ok(SuccessResponse {
-        msg: "success",
-        data: Some(Json(TokenResponse { tokens })),
+async fn activate(
+    db: &PgPool,
+    redis: &mut Redis,
+    uid: AccountId,
+    remember_me: bool,
+) -> AppResult<ActivationResponse> {
+    let user = Account::activate_by_uid(db, uid)
+        .await?
+        .ok_or(AuthError(AuthInnerError::UserAlreadyActivated))?;
+    let tokens =
+        Claims::generate_tokens_for_user(&user, remember_me, None, redis)
+            .await?;
+
+    Ok(ActivationResponse {
+        tokens,
+        user: UserResponse::from(user),
     })
 }
 
@@ -238,12 +523,14 @@ pub async fn change_password_handler(
     ));
 
     if let Some(stored) = redis.get::<String>(&key).await? {
-        if stored == body.code {
+        if stored == validation::validate_code(&body.code)? {
+            validation::validate_password_strength(&body.password)?;
             let item = ResetPasswordSchema {
                 uid: claims.uid,
                 password: crypto::hash_password(body.password.as_bytes())?,
             };
-            Account::update_password_by_uid(state.get_db(), &item).await?;
+            Account::update_password_by_uid(state.get_write_db(), &item)
+                .await?;
             redis.del(&key).await?;
         } else {
             return Err(AuthError(AuthInnerError::WrongCode));
@@ -255,3 +542,393 @@ pub async fn change_password_handler(
         data: None::<()>,
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use assert_json_diff::assert_json_eq;
+    use axum::http::StatusCode;
+
+    use super::*;
+    use crate::{
+        app::{entity::event::Event, service::Services},
+        library::{error::AppError, mqer::Subscriber, Dber, Redisor},
+    };
+
+    #[test]
+    fn test_activation_link() {
+        assert_eq!(
+            activation_link("https://iwi/activate", "a.b.c"),
+            "https://iwi/activate?token=a.b.c"
+        );
+        assert_eq!(
+            activation_link("https://iwi/activate?lang=fr", "a.b.c"),
+            "https://iwi/activate?lang=fr&token=a.b.c"
+        );
+    }
+
+    #[sqlx::test(fixtures(
+        path = "../../../../../fixtures",
+        scripts("account")
+    ))]
+    #[ignore]
+    async fn test_email_available(pool: PgPool) -> sqlx::Result<()> {
+        for (email, available) in [
+            ("vainjoker@tuta.io", false),
+            (" VainJoker@Tuta.IO ", false),
+            ("nobody@tuta.io", true),
+        ] {
+            let query = EmailAvailableQuery {
+                email: email.to_string(),
+            };
+            assert_eq!(
+                email_available(&pool, &query).await.unwrap(),
+                available
+            );
+        }
+
+        Ok(())
+    }
+
+    #[sqlx::test(fixtures(
+        path = "../../../../../fixtures",
+        scripts("account")
+    ))]
+    #[ignore]
+    async fn test_mixed_case_email_taken(pool: PgPool) -> sqlx::Result<()> {
+        // Stored as typed, like accounts registered before normalizing.
+        sqlx::query(
+            "INSERT INTO bw_account (name, email, password) VALUES ($1, $2, $3)",
+        )
+        .bind("Mixed")
+        .bind("Mixed.Case@Tuta.IO")
+        .bind("password")
+        .execute(&pool)
+        .await?;
+
+        for email in ["mixed.case@tuta.io", "MIXED.CASE@TUTA.IO"] {
+            let query = EmailAvailableQuery {
+                email: email.to_string(),
+            };
+            assert!(!email_available(&pool, &query).await.unwrap());
+        }
+
+        Ok(())
+    }
+
+    #[sqlx::test(fixtures(
+        path = "../../../../../fixtures",
+        scripts("account")
+    ))]
+    #[ignore]
+    async fn test_register_rejects_case_variant_email(
+        pool: PgPool,
+    ) -> sqlx::Result<()> {
+        // Stored as typed, like accounts registered before normalizing.
+        sqlx::query(
+            "INSERT INTO bw_account (name, email, password) VALUES ($1, $2, $3)",
+        )
+        .bind("Mixed")
+        .bind("Mixed.Case@Tuta.IO")
+        .bind("password")
+        .execute(&pool)
+        .await?;
+
+        for (name, email) in [
+            ("Lower", "mixed.case@tuta.io"),
+            ("Upper", " MIXED.CASE@TUTA.IO "),
+        ] {
+            let item = RegisterSchema {
+                name: name.to_string(),
+                email: validation::normalize_email(email),
+                password: "password".to_string(),
+            };
+            assert!(matches!(
+                register(&pool, &item).await,
+                Err(AuthError(AuthInnerError::UserAlreadyExists))
+            ));
+        }
+
+        Ok(())
+    }
+
+    #[sqlx::test(fixtures(
+        path = "../../../../../fixtures",
+        scripts("account")
+    ))]
+    #[ignore]
+    async fn test_register_rejects_duplicate_name(
+        pool: PgPool,
+    ) -> sqlx::Result<()> {
+        let request = |email: &str| RegisterUserRequest {
+            name: "Twin".to_string(),
+            email: email.to_string(),
+            password: "password".to_string(),
+        };
+        let first = request("twin1@tuta.io");
+        ensure_name_available(&pool, &first.name).await.unwrap();
+        let item = RegisterSchema {
+            name: first.name,
+            email: first.email,
+            password: first.password,
+        };
+        register(&pool, &item).await.unwrap();
+
+        assert!(matches!(
+            ensure_name_available(&pool, &request("twin2@tuta.io").name).await,
+            Err(AuthError(AuthInnerError::NameAlreadyTaken))
+        ));
+
+        Ok(())
+    }
+
+    #[sqlx::test(fixtures(
+        path = "../../../../../fixtures",
+        scripts("account")
+    ))]
+    #[ignore]
+    async fn test_register_publishes_user_registered(
+        pool: PgPool,
+    ) -> sqlx::Result<()> {
+        std::env::set_var("IWI_APP__PUBLISH_EVENTS", "true");
+        cfg::init("./fixtures/config.toml");
+        let state = Arc::new(AppState {
+            db: Dber::new(pool.clone(), None),
+            redis: Redisor::init(),
+            services: Services::init().await,
+        });
+        let mqer = state.get_mq().unwrap();
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let sink = received.clone();
+        let delegate = Subscriber::new(
+            move |message| {
+                sink.lock().unwrap().push(message);
+                Ok(())
+            },
+            mqer.clone(),
+        );
+        mqer.topic_receive(
+            constants::MQ_EVENT_EXCHANGE,
+            "app.dev.test_user_registered",
+            UserRegistered::ROUTING_KEY,
+            "app.dev.test_user_registered_tag",
+            delegate,
+        )
+        .await
+        .unwrap();
+
+        let email = format!("{}@tuta.io", uuid::Uuid::new_v4());
+        let request = RegisterUserRequest {
+            name: "Announced".to_string(),
+            email: email.to_uppercase(),
+            password: "Password1!".to_string(),
+        };
+        register_user_handler(State(state.clone()), JsonOrForm(request))
+            .await
+            .unwrap();
+        let user = Account::fetch_user_by_email(&pool, &email)
+            .await
+            .unwrap()
+            .unwrap();
+        tokio::time::sleep(Duration::from_secs(1)).await;
+
+        let event = received
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|message| {
+                serde_json::from_str::<serde_json::Value>(message).unwrap()
+            })
+            .find(|event| event["email"] == email.as_str())
+            .unwrap();
+        assert_json_eq!(
+            event,
+            serde_json::json!({
+                "uid": user.id,
+                "email": email,
+                "language": "en-US",
+            })
+        );
+
+        Ok(())
+    }
+
+    #[sqlx::test(fixtures(
+        path = "../../../../../fixtures",
+        scripts("account")
+    ))]
+    #[ignore]
+    async fn test_concurrent_registrations(pool: PgPool) -> sqlx::Result<()> {
+        let item = |name: &str| RegisterSchema {
+            name: name.to_string(),
+            email: "race@tuta.io".to_string(),
+            password: "password".to_string(),
+        };
+        let (first, second) = (item("Racer 1"), item("Racer 2"));
+        let (first, second) =
+            tokio::join!(register(&pool, &first), register(&pool, &second));
+
+        let (ok, err): (Vec<_>, Vec<_>) =
+            [first, second].into_iter().partition(Result::is_ok);
+        assert_eq!(ok.len(), 1);
+        let err = err.into_iter().next().unwrap().unwrap_err();
+        assert!(matches!(err, AuthError(AuthInnerError::UserAlreadyExists)));
+        assert_eq!(
+            AppError::select_status_code(&err),
+            (StatusCode::CONFLICT, 10004)
+        );
+
+        Ok(())
+    }
+
+    #[sqlx::test(fixtures(
+        path = "../../../../../fixtures",
+        scripts("account")
+    ))]
+    #[ignore]
+    async fn test_activation_returns_active_user(
+        pool: PgPool,
+    ) -> sqlx::Result<()> {
+        cfg::init("./fixtures/config.toml");
+        let redisor = crate::library::Redisor::init();
+        let mut redis = redisor.get_redis().await.unwrap();
+        let uid = AccountId(6192889942050345985);
+        let key = redis.key(&format!(
+            "{}:{}",
+            uid,
+            constants::REDIS_ACTIVE_ACCOUNT_KEY
+        ));
+        redis
+            .set_ex(&key, "ABC234", Duration::from_secs(60))
+            .await
+            .unwrap();
+
+        assert!(matches!(
+            activate_account(&pool, &mut redis, uid, "WRONG1", false).await,
+            Err(AuthError(AuthInnerError::WrongCode))
+        ));
+        let activation =
+            activate_account(&pool, &mut redis, uid, "ABC234", false)
+                .await
+                .unwrap();
+        assert_eq!(activation.user.status, AccountStatus::Active);
+        let claims = Claims::parse_token(
+            &activation.tokens.access_token,
+            TokenType::ACCESS,
+            true,
+        )
+        .unwrap();
+        assert_eq!(claims.status, AccountStatus::Active);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn test_name_change_cooldown() {
+        cfg::init("./fixtures/config.toml");
+        let redisor = crate::library::Redisor::init();
+        let mut redis = redisor.get_redis().await.unwrap();
+        let key = format!("{}:test", constants::REDIS_NAME_CHANGE_KEY);
+        redis.del(&key).await.unwrap();
+        let cooldown = Duration::from_secs(60);
+
+        start_cooldown(&mut redis, &key, cooldown).await.unwrap();
+        match start_cooldown(&mut redis, &key, cooldown).await {
+            Err(ApiError(ApiInnerError::TooManyRequests {
+                retry_after_secs,
+            })) => assert!((1..=60).contains(&retry_after_secs)),
+            other => panic!("unexpected result: {other:?}"),
+        }
+
+        redis.del(&key).await.unwrap();
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn test_failed_rename_not_charged() {
+        cfg::init("./fixtures/config.toml");
+        let redisor = crate::library::Redisor::init();
+        let mut redis = redisor.get_redis().await.unwrap();
+        let key = format!("{}:test_failed", constants::REDIS_NAME_CHANGE_KEY);
+        redis.del(&key).await.unwrap();
+        let cooldown = Duration::from_secs(60);
+
+        // Lost the race for the name after the availability check.
+        let failed = with_cooldown(&mut redis, &key, cooldown, async {
+            Err::<(), _>(AuthError(AuthInnerError::NameAlreadyTaken))
+        })
+        .await;
+        assert!(matches!(
+            failed,
+            Err(AuthError(AuthInnerError::NameAlreadyTaken))
+        ));
+
+        with_cooldown(&mut redis, &key, cooldown, async { Ok(()) })
+            .await
+            .unwrap();
+        assert!(matches!(
+            with_cooldown(&mut redis, &key, cooldown, async { Ok(()) }).await,
+            Err(ApiError(ApiInnerError::TooManyRequests { .. }))
+        ));
+
+        redis.del(&key).await.unwrap();
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn test_code_cooldown_and_validity() {
+        cfg::init("./fixtures/config.toml");
+        let redisor = crate::library::Redisor::init();
+        let mut redis = redisor.get_redis().await.unwrap();
+        let key = "test_issue_code";
+        let cooldown_key = format!("{key}:cooldown");
+        for k in [key, &cooldown_key] {
+            redis.del(k).await.unwrap();
+        }
+
+        // A code outliving its cooldown can be replaced while still valid.
+        let long_lived = CodeConfig {
+            validity: 10,
+            cooldown: 1,
+        };
+        let first = issue_code(&mut redis, key, long_lived).await.unwrap();
+        assert!(matches!(
+            issue_code(&mut redis, key, long_lived).await,
+            Err(ApiError(ApiInnerError::TooManyRequests { .. }))
+        ));
+        tokio::time::sleep(Duration::from_millis(1100)).await;
+        assert_eq!(redis.get::<String>(key).await.unwrap(), Some(first));
+        issue_code(&mut redis, key, long_lived).await.unwrap();
+
+        // A code expiring within its cooldown can't be replaced right away.
+        tokio::time::sleep(Duration::from_millis(1100)).await;
+        let short_lived = CodeConfig {
+            validity: 1,
+            cooldown: 10,
+        };
+        issue_code(&mut redis, key, short_lived).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(1100)).await;
+        assert_eq!(redis.get::<String>(key).await.unwrap(), None);
+        assert!(matches!(
+            issue_code(&mut redis, key, short_lived).await,
+            Err(ApiError(ApiInnerError::TooManyRequests { .. }))
+        ));
+
+        for k in [key, &cooldown_key] {
+            redis.del(k).await.unwrap();
+        }
+    }
+
+    #[test]
+    fn test_check_account_quota() {
+        assert!(check_account_quota(9, 10).is_ok());
+        for count in [10, 11] {
+            assert!(matches!(
+                check_account_quota(count, 10),
+                Err(ApiError(ApiInnerError::AccountQuotaExceeded))
+            ));
+        }
+    }
+}
@@ -0,0 +1,110 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::{Query, State},
+    response::IntoResponse,
+    Json,
+};
+use validator::Validate;
+
+use crate::{
+    app::{
+        api::{
+            controller::health::check_dependencies, extractor::ValidatedJson,
+            middleware::metrics,
+        },
+        bootstrap::{
+            constants::{MQ_SEND_EMAIL_DLX, MQ_SEND_EMAIL_QUEUE},
+            AppState,
+        },
+        entity::{
+            admin::{
+                AccountSummary, BulkAccountsRequest, RequeueResponse,
+                StatsResponse, SuspendAccountsResponse,
+            },
+            common::{Page, PageQuery, SuccessResponse},
+            health::ReadinessResponse,
+        },
+        service::{
+            admin_guard::{self, SensitiveAction},
+            jwt_service::Claims,
+        },
+    },
+    library::{
+        cfg,
+        error::{ApiInnerError, AppResult},
+    },
+    models::account::Account,
+};
+
+pub async fn requeue_email_dlq_handler(
+    State(state): State<Arc<AppState>>,
+) -> AppResult<impl IntoResponse> {
+    let batch = cfg::config().app.admin.dlq_requeue_batch;
+    let requeued = state
+        .get_mq()?
+        .requeue_dead_letters(MQ_SEND_EMAIL_QUEUE, MQ_SEND_EMAIL_DLX, batch)
+        .await?;
+    tracing::info!("Requeued {requeued} dead-lettered emails");
+
+    Ok(SuccessResponse {
+        msg: "success",
+        data: Some(Json(RequeueResponse { requeued })),
+    })
+}
+
+/// Request totals per route since the server started, and the state of
+/// the dependencies.
+pub async fn stats_handler(
+    State(state): State<Arc<AppState>>,
+) -> AppResult<impl IntoResponse> {
+    let stats = StatsResponse {
+        routes: metrics::metrics().snapshot(),
+        dependencies: ReadinessResponse::new(&check_dependencies(&state).await),
+    };
+
+    Ok(SuccessResponse {
+        msg: "success",
+        data: Some(Json(stats)),
+    })
+}
+
+pub async fn list_accounts_handler(
+    State(state): State<Arc<AppState>>,
+    Query(page): Query<PageQuery>,
+) -> AppResult<impl IntoResponse> {
+    page.validate().map_err(ApiInnerError::ValidationError)?;
+    let db = state.get_read_db();
+    let accounts = Account::list_accounts(db, page.limit, page.offset).await?;
+    let total = Account::count_accounts(db).await?;
+    let items = accounts.into_iter().map(AccountSummary::from).collect();
+
+    Ok(SuccessResponse {
+        msg: "success",
+        data: Some(Json(Page::new(items, total, page))),
+    })
+}
+
+/// Suspends the given accounts and invalidates their sessions.
+pub async fn suspend_accounts_handler(
+    State(state): State<Arc<AppState>>,
+    claims: Claims,
+    ValidatedJson(body): ValidatedJson<BulkAccountsRequest>,
+) -> AppResult<impl IntoResponse> {
+    admin_guard::record(&state, claims.uid, SensitiveAction::Suspension)
+        .await?;
+    let suspended =
+        Account::suspend_by_uids(state.get_write_db(), &body.uids).await?;
+    Claims::invalidate_sessions_of(&suspended, &mut state.get_redis().await?)
+        .await?;
+    tracing::info!(
+        "Admin {} suspended {} accounts",
+        claims.uid,
+        suspended.len()
+    );
+
+    Ok(SuccessResponse {
+        msg: "success",
+        data: Some(Json(SuspendAccountsResponse { suspended })),
+    })
+}
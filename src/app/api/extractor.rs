@@ -0,0 +1,287 @@
+use std::convert::Infallible;
+
+use axum::{
+    async_trait,
+    extract::{FromRequest, FromRequestParts, Request},
+    http::{
+        header::{ACCEPT_LANGUAGE, CONTENT_TYPE},
+        request::Parts,
+    },
+    response::{IntoResponse, Response},
+    Form, Json,
+};
+use serde::de::DeserializeOwned;
+use validator::Validate;
+
+use crate::{
+    library::error::{ApiInnerError, AppError::ApiError, AppResult},
+    models::types::Language,
+};
+
+/// The caller's preferred supported [`Language`] from `Accept-Language`,
+/// [`Language::default`] when the header is absent or names nothing we
+/// support.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct AcceptLanguage(pub Language);
+
+impl AcceptLanguage {
+    pub fn parse(header: &str) -> Self {
+        let mut ranges: Vec<(&str, f32)> = header
+            .split(',')
+            .filter_map(|range| {
+                let mut parts = range.split(';');
+                let tag = parts.next()?.trim();
+                let quality = parts
+                    .find_map(|param| param.trim().strip_prefix("q="))
+                    .map_or(Some(1.0), |q| q.trim().parse::<f32>().ok())?;
+                (!tag.is_empty() && quality > 0.0).then_some((tag, quality))
+            })
+            .collect();
+        // Stable, so equally weighted ranges keep the caller's order.
+        ranges.sort_by(|a, b| b.1.total_cmp(&a.1));
+
+        let language = ranges
+            .into_iter()
+            .find_map(|(tag, _)| Language::from_tag(tag))
+            .unwrap_or_default();
+        Self(language)
+    }
+}
+
+/// A JSON body that also passed its [`Validate`] rules, which are rejected
+/// as [`ApiInnerError::ValidationError`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ValidatedJson<T>(pub T);
+
+#[async_trait]
+impl<T, S> FromRequest<S> for ValidatedJson<T>
+where
+    T: DeserializeOwned + Validate,
+    S: Send + Sync,
+{
+    type Rejection = Response;
+
+    async fn from_request(
+        req: Request,
+        state: &S,
+    ) -> Result<Self, Self::Rejection> {
+        let Json(body) = Json::<T>::from_request(req, state)
+            .await
+            .map_err(IntoResponse::into_response)?;
+        validate(body)
+            .map(Self)
+            .map_err(IntoResponse::into_response)
+    }
+}
+
+/// Like [`ValidatedJson`], but also accepts a form-encoded body, for
+/// endpoints posted to by plain HTML forms.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct JsonOrForm<T>(pub T);
+
+#[async_trait]
+impl<T, S> FromRequest<S> for JsonOrForm<T>
+where
+    T: DeserializeOwned + Validate,
+    S: Send + Sync,
+{
+    type Rejection = Response;
+
+    async fn from_request(
+        req: Request,
+        state: &S,
+    ) -> Result<Self, Self::Rejection> {
+        let form = req
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(|value| {
+                value.starts_with("application/x-www-form-urlencoded")
+            });
+        if form {
+            let Form(body) =
+                Form::<T>::from_request(req, state).await.map_err(|e| {
+                    ApiError(ApiInnerError::AxumFormRejection(e))
+                        .into_response()
+                })?;
+            validate(body)
+                .map(Self)
+                .map_err(IntoResponse::into_response)
+        } else {
+            let ValidatedJson(body) =
+                ValidatedJson::<T>::from_request(req, state).await?;
+            Ok(Self(body))
+        }
+    }
+}
+
+fn validate<T: Validate>(body: T) -> AppResult<T> {
+    body.validate().map_err(ApiInnerError::ValidationError)?;
+    Ok(body)
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for AcceptLanguage
+where
+    S: Send + Sync,
+{
+    type Rejection = Infallible;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        _state: &S,
+    ) -> Result<Self, Self::Rejection> {
+        Ok(parts
+            .headers
+            .get(ACCEPT_LANGUAGE)
+            .and_then(|value| value.to_str().ok())
+            .map(Self::parse)
+            .unwrap_or_default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{
+        body::Body,
+        http::{header::CONTENT_TYPE, StatusCode},
+        routing::post,
+        Router,
+    };
+    use serde_json::json;
+    use tower::ServiceExt;
+
+    use super::*;
+    use crate::app::entity::account::{LoginUserRequest, RegisterUserRequest};
+
+    #[test]
+    fn test_parse_orders_by_quality() {
+        assert_eq!(
+            AcceptLanguage::parse("en-US;q=0.5, fr-FR;q=0.9, zh-CN;q=0.7"),
+            AcceptLanguage(Language::FrFr)
+        );
+        assert_eq!(
+            AcceptLanguage::parse("es-ES, zh-CN"),
+            AcceptLanguage(Language::EsEs)
+        );
+        assert_eq!(
+            AcceptLanguage::parse("zh-CN;q=0, es;q=0.1"),
+            AcceptLanguage(Language::EsEs)
+        );
+    }
+
+    #[test]
+    fn test_parse_falls_back() {
+        // Primary language of an unsupported region.
+        assert_eq!(
+            AcceptLanguage::parse("fr-CA"),
+            AcceptLanguage(Language::FrFr)
+        );
+        // Unsupported languages are skipped.
+        assert_eq!(
+            AcceptLanguage::parse("de-DE, ja;q=0.9, zh-TW;q=0.8"),
+            AcceptLanguage(Language::ZhCn)
+        );
+        assert_eq!(
+            AcceptLanguage::parse("de-DE, *;q=0.5"),
+            AcceptLanguage(Language::default())
+        );
+        assert_eq!(AcceptLanguage::parse(""), AcceptLanguage(Language::EnUs));
+    }
+
+    #[tokio::test]
+    async fn test_validated_json_rejects_invalid_body() {
+        #[allow(clippy::unused_async)]
+        async fn register(
+            ValidatedJson(_): ValidatedJson<RegisterUserRequest>,
+        ) -> StatusCode {
+            StatusCode::OK
+        }
+        let app = Router::new().route("/register", post(register));
+        let status = |body: serde_json::Value| {
+            let request = Request::builder()
+                .method("POST")
+                .uri("/register")
+                .header(CONTENT_TYPE, "application/json")
+                .body(Body::from(body.to_string()))
+                .unwrap();
+            let app = app.clone();
+            async move { app.oneshot(request).await.unwrap().status() }
+        };
+
+        let invalid_email =
+            json!({ "name": "iwi", "email": "iwi", "password": "password" });
+        assert_eq!(
+            status(invalid_email).await,
+            StatusCode::UNPROCESSABLE_ENTITY
+        );
+        let short_password =
+            json!({ "name": "iwi", "email": "iwi@tuta.io", "password": "abc" });
+        assert_eq!(
+            status(short_password).await,
+            StatusCode::UNPROCESSABLE_ENTITY
+        );
+        let valid = json!({
+            "name": "iwi",
+            "email": "iwi@tuta.io",
+            "password": "password",
+        });
+        assert_eq!(status(valid).await, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_json_or_form_accepts_both() {
+        #[allow(clippy::unused_async)]
+        async fn login(
+            JsonOrForm(body): JsonOrForm<LoginUserRequest>,
+        ) -> String {
+            format!("{}:{}", body.email_or_name, body.remember_me)
+        }
+        let app = Router::new().route("/login", post(login));
+        let login = |content_type: &str, body: String| {
+            let request = Request::builder()
+                .method("POST")
+                .uri("/login")
+                .header(CONTENT_TYPE, content_type)
+                .body(Body::from(body))
+                .unwrap();
+            let app = app.clone();
+            async move {
+                let response = app.oneshot(request).await.unwrap();
+                let status = response.status();
+                let body =
+                    axum::body::to_bytes(response.into_body(), usize::MAX)
+                        .await
+                        .unwrap();
+                (status, String::from_utf8(body.to_vec()).unwrap())
+            }
+        };
+
+        let json = json!({
+            "email_or_name": "iwi",
+            "password": "password",
+            "remember_me": true,
+        });
+        assert_eq!(
+            login("application/json", json.to_string()).await,
+            (StatusCode::OK, "iwi:true".to_string())
+        );
+        assert_eq!(
+            login(
+                "application/x-www-form-urlencoded",
+                "email_or_name=iwi&password=password&remember_me=true".into()
+            )
+            .await,
+            (StatusCode::OK, "iwi:true".to_string())
+        );
+        assert_eq!(
+            login(
+                "application/x-www-form-urlencoded",
+                format!("email_or_name={}", "i".repeat(256))
+            )
+            .await
+            .0,
+            StatusCode::UNPROCESSABLE_ENTITY
+        );
+    }
+}
@@ -0,0 +1,213 @@
+use std::{
+    collections::HashMap,
+    fs::OpenOptions,
+    io::{self, Write},
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+};
+
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    http::Method,
+    middleware::Next,
+    response::Response,
+};
+use chrono::Local;
+use serde::Serialize;
+use tracing_appender::non_blocking::WorkerGuard;
+
+use super::{auth::Caller, req_id::REQUEST_ID_HEADER};
+use crate::{
+    library::cfg::AuditConfig,
+    models::types::{AccountId, Language},
+};
+
+/// Where audit records go, one JSON object per line.
+pub struct AuditSink {
+    writer: Mutex<Box<dyn Write + Send>>,
+    regions: HashMap<String, String>,
+    _guard: Option<WorkerGuard>,
+}
+
+#[derive(Debug, Serialize)]
+struct AuditRecord<'a> {
+    timestamp: String,
+    request_id: Option<&'a str>,
+    uid: Option<AccountId>,
+    /// The caller's data residency zone.
+    region: Option<&'a str>,
+    ip: Option<String>,
+    method: &'a str,
+    path: &'a str,
+    status: u16,
+}
+
+impl AuditSink {
+    pub fn new(writer: impl Write + Send + 'static) -> Self {
+        Self {
+            writer: Mutex::new(Box::new(writer)),
+            regions: HashMap::new(),
+            _guard: None,
+        }
+    }
+
+    /// Overrides the zones of `Language::region`, by language tag.
+    #[must_use]
+    pub fn with_regions(mut self, regions: HashMap<String, String>) -> Self {
+        self.regions = regions;
+        self
+    }
+
+    fn region(&self, language: Language) -> &str {
+        self.regions
+            .get(language.tag())
+            .map_or_else(|| language.region(), String::as_str)
+    }
+
+    /// Appends to the configured file without blocking requests, `None`
+    /// when auditing is off.
+    pub fn from_config(cfg: &AuditConfig) -> io::Result<Option<Self>> {
+        let Some(path) = &cfg.path else {
+            return Ok(None);
+        };
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let (writer, guard) = tracing_appender::non_blocking(file);
+        Ok(Some(Self {
+            writer: Mutex::new(Box::new(writer)),
+            regions: cfg.regions.clone(),
+            _guard: Some(guard),
+        }))
+    }
+
+    fn write(&self, record: &AuditRecord<'_>) {
+        let mut line = match serde_json::to_vec(record) {
+            Ok(line) => line,
+            Err(e) => {
+                tracing::error!("Failed to serialize audit record: {e}");
+                return;
+            }
+        };
+        line.push(b'\n');
+        let mut writer = self.writer.lock().unwrap_or_else(|e| e.into_inner());
+        if let Err(e) = writer.write_all(&line) {
+            tracing::error!("Failed to write audit record: {e}");
+        }
+    }
+}
+
+/// Records who made each mutating request, what it was and how it ended.
+pub async fn handle(
+    State(sink): State<Arc<AuditSink>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if matches!(
+        *request.method(),
+        Method::GET | Method::HEAD | Method::OPTIONS
+    ) {
+        return next.run(request).await;
+    }
+
+    let method = request.method().clone();
+    let path = request.uri().path().to_string();
+    let request_id = request
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .map(ToString::to_string);
+    let ip = request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|info| info.0.ip().to_string());
+
+    let response = next.run(request).await;
+    let caller = response.extensions().get::<Caller>();
+
+    sink.write(&AuditRecord {
+        timestamp: Local::now().to_rfc3339(),
+        request_id: request_id.as_deref(),
+        uid: caller.map(|caller| caller.uid),
+        region: caller
+            .and_then(|caller| caller.language)
+            .map(|language| sink.region(language)),
+        ip,
+        method: method.as_str(),
+        path: &path,
+        status: response.status().as_u16(),
+    });
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{
+        body::Body, http::StatusCode, middleware::from_fn_with_state,
+        response::IntoResponse, routing::post, Router,
+    };
+    use serde_json::Value;
+    use tower::ServiceExt;
+
+    use super::*;
+
+    #[derive(Clone, Default)]
+    struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Buffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_mutating_request_is_audited() {
+        #[allow(clippy::unused_async)]
+        async fn create() -> Response {
+            let mut response = StatusCode::CREATED.into_response();
+            response.extensions_mut().insert(Caller {
+                uid: AccountId(7),
+                language: Some(Language::FrFr),
+            });
+            response
+        }
+        let buffer = Buffer::default();
+        let app = Router::new()
+            .route("/items", post(create).get(|| async { "items" }))
+            .layer(from_fn_with_state(
+                Arc::new(AuditSink::new(buffer.clone()).with_regions(
+                    HashMap::from([(
+                        "fr-FR".to_string(),
+                        "eu-west".to_string(),
+                    )]),
+                )),
+                handle,
+            ));
+        let request = |method: &str| {
+            Request::builder()
+                .method(method)
+                .uri("/items")
+                .header("x-request-id", "req-1")
+                .body(Body::empty())
+                .unwrap()
+        };
+
+        app.clone().oneshot(request("GET")).await.unwrap();
+        app.oneshot(request("POST")).await.unwrap();
+
+        let contents =
+            String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        let records = contents.lines().collect::<Vec<_>>();
+        assert_eq!(records.len(), 1);
+        let record = serde_json::from_str::<Value>(records[0]).unwrap();
+        assert_eq!(record["request_id"], "req-1");
+        assert_eq!(record["uid"], 7);
+        assert_eq!(record["region"], "eu-west");
+        assert_eq!(record["method"], "POST");
+        assert_eq!(record["path"], "/items");
+        assert_eq!(record["status"], 201);
+        assert!(record["timestamp"].is_string());
+    }
+}
@@ -1,18 +1,64 @@
+use std::sync::Arc;
+
 use axum::{
-    extract::Request, http::header::AUTHORIZATION, middleware::Next,
+    extract::Request,
+    http::{header::AUTHORIZATION, HeaderName, HeaderValue},
+    middleware::Next,
     response::Response,
 };
 
 use crate::{
-    app::service::jwt_service::{Claims, TokenType},
-    library::error::{AppError::AuthError, AppResult, AuthInnerError},
+    app::{
+        bootstrap::AppState,
+        service::jwt_service::{Claims, TokenType},
+    },
+    library::{
+        cfg,
+        error::{AppError::AuthError, AppResult, AuthInnerError},
+    },
+    models::types::{AccountId, Language},
 };
 
+/// Seconds until the caller's access token expires, so clients can refresh
+/// it beforehand without decoding it.
+pub const TOKEN_EXPIRES_IN_HEADER: HeaderName =
+    HeaderName::from_static("x-token-expires-in");
+
+/// The verified caller, attached to the response for the outer
+/// middlewares.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Caller {
+    pub uid: AccountId,
+    pub language: Option<Language>,
+}
+
+/// What a route requires from the caller's access token.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuthRequirement {
+    /// No token is needed.
+    Open,
+    /// A valid token of an account that isn't suspended.
+    Authenticated,
+    /// A valid token of an active account.
+    Active,
+    /// A valid token of an active account listed in `app.admin.uids`.
+    Admin,
+}
+
+/// Checks the access token against `requirement`, and against the revoked
+/// tokens when `state` is given.
 pub async fn handle(
     request: Request,
     next: Next,
-    verified: bool,
+    requirement: AuthRequirement,
+    state: Option<Arc<AppState>>,
 ) -> AppResult<Response> {
+    let verified = match requirement {
+        AuthRequirement::Open => return Ok(next.run(request).await),
+        AuthRequirement::Authenticated => false,
+        AuthRequirement::Active | AuthRequirement::Admin => true,
+    };
+
     let token = request
         .headers()
         .get(AUTHORIZATION)
@@ -20,7 +66,87 @@ pub async fn handle(
         .and_then(|auth_value| auth_value.strip_prefix("Bearer "))
         .ok_or(AuthError(AuthInnerError::InvalidToken))?;
 
-    Claims::parse_token(token, TokenType::ACCESS, verified)?;
+    let claims = Claims::parse_token(token, TokenType::ACCESS, verified)?;
+    if requirement == AuthRequirement::Admin
+        && !cfg::config().app.admin.uids.contains(&claims.uid.0)
+    {
+        return Err(AuthError(AuthInnerError::PermissionDenied));
+    }
+    if let Some(state) = state {
+        claims
+            .ensure_not_revoked(&mut state.get_redis().await?)
+            .await?;
+    }
+
+    let mut response = next.run(request).await;
+    response.extensions_mut().insert(Caller {
+        uid: claims.uid,
+        language: claims.language,
+    });
+    let now = chrono::Utc::now().timestamp().unsigned_abs();
+    let expires_in = (claims.exp as u64).saturating_sub(now);
+    response
+        .headers_mut()
+        .insert(TOKEN_EXPIRES_IN_HEADER, HeaderValue::from(expires_in));
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{
+        body::Body,
+        http::{header::AUTHORIZATION, Request, StatusCode},
+        middleware::from_fn,
+        routing::get,
+        Router,
+    };
+    use tower::ServiceExt;
+
+    use super::*;
+    use crate::{
+        app::service::jwt_service::UserInfo, models::types::AccountStatus,
+    };
+
+    #[allow(clippy::unused_async)]
+    async fn ok_handler() -> StatusCode {
+        StatusCode::OK
+    }
+
+    #[tokio::test]
+    async fn test_token_expiry_hint() {
+        let app =
+            Router::new()
+                .route("/me", get(ok_handler))
+                .route_layer(from_fn(|req, next| {
+                    handle(req, next, AuthRequirement::Authenticated, None)
+                }));
+        let token = Claims::test_access_token(&UserInfo {
+            uid: AccountId(1),
+            email: "test@test.com".to_string(),
+            status: AccountStatus::Active,
+            remember_me: false,
+            epoch: 0,
+            language: Language::EnUs,
+        });
+        let request = Request::builder()
+            .uri("/me")
+            .header(AUTHORIZATION, format!("Bearer {token}"))
+            .body(Body::empty())
+            .unwrap();
 
-    Ok(next.run(request).await)
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let claims =
+            Claims::parse_token(&token, TokenType::ACCESS, false).unwrap();
+        let lifetime = (claims.exp - claims.iat) as u64;
+        let expires_in = response.headers()[TOKEN_EXPIRES_IN_HEADER]
+            .to_str()
+            .unwrap()
+            .parse::<u64>()
+            .unwrap();
+        assert!(
+            (lifetime - 5..=lifetime).contains(&expires_in),
+            "{expires_in} of {lifetime}"
+        );
+    }
 }
@@ -0,0 +1,97 @@
+use std::sync::Arc;
+
+use axum::body::HttpBody;
+use hyper::{header::CONTENT_TYPE, Response};
+use tower_http::compression::{
+    predicate::{NotForContentType, SizeAbove},
+    CompressionLayer, Predicate,
+};
+
+use crate::library::cfg::CompressionConfig;
+
+/// Decides which responses get compressed, see [`CompressionConfig`].
+#[derive(Debug, Clone)]
+pub struct CompressionPredicate {
+    enabled: bool,
+    min_size: SizeAbove,
+    excluded: Arc<[NotForContentType]>,
+}
+
+impl From<&CompressionConfig> for CompressionPredicate {
+    fn from(cfg: &CompressionConfig) -> Self {
+        Self {
+            enabled: cfg.enabled,
+            min_size: SizeAbove::new(cfg.min_size),
+            excluded: cfg
+                .excluded_content_types
+                .iter()
+                .map(|content_type| NotForContentType::new(content_type))
+                .collect(),
+        }
+    }
+}
+
+impl Predicate for CompressionPredicate {
+    fn should_compress<B>(&self, response: &Response<B>) -> bool
+    where
+        B: HttpBody,
+    {
+        self.enabled
+            && response.headers().contains_key(CONTENT_TYPE)
+            && self.min_size.should_compress(response)
+            && self
+                .excluded
+                .iter()
+                .all(|excluded| excluded.should_compress(response))
+    }
+}
+
+pub fn layer(
+    cfg: &CompressionConfig,
+) -> CompressionLayer<CompressionPredicate> {
+    CompressionLayer::new().compress_when(CompressionPredicate::from(cfg))
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{
+        body::Body,
+        http::{header, Request},
+        routing::get,
+        Router,
+    };
+    use tower::ServiceExt;
+
+    use super::*;
+
+    async fn content_encoding(content_type: &'static str, size: usize) -> bool {
+        let app = Router::new()
+            .route(
+                "/",
+                get(move || async move {
+                    ([(header::CONTENT_TYPE, content_type)], "a".repeat(size))
+                }),
+            )
+            .layer(layer(&CompressionConfig::default()));
+        let request = Request::builder()
+            .uri("/")
+            .header(header::ACCEPT_ENCODING, "gzip")
+            .body(Body::empty())
+            .unwrap();
+
+        let response = app.oneshot(request).await.unwrap();
+        response.headers().contains_key(header::CONTENT_ENCODING)
+    }
+
+    #[tokio::test]
+    async fn test_compresses_large_json() {
+        assert!(content_encoding("application/json", 4096).await);
+    }
+
+    #[tokio::test]
+    async fn test_skips_excluded_content_type_and_small_body() {
+        assert!(!content_encoding("application/gzip", 4096).await);
+        assert!(!content_encoding("image/png", 4096).await);
+        assert!(!content_encoding("application/json", 16).await);
+    }
+}
@@ -1,39 +1,146 @@
+use std::sync::Arc;
+
 use axum::{
-    extract::Request,
+    extract::{Request, State},
     http::{self, HeaderMap, HeaderValue, Method, StatusCode},
     middleware::Next,
     response::{IntoResponse, Response},
 };
 use http::header::{
     ACCESS_CONTROL_ALLOW_CREDENTIALS, ACCESS_CONTROL_ALLOW_HEADERS,
-    ACCESS_CONTROL_ALLOW_METHODS, ACCESS_CONTROL_ALLOW_ORIGIN,
+    ACCESS_CONTROL_ALLOW_METHODS, ACCESS_CONTROL_ALLOW_ORIGIN, ORIGIN, VARY,
 };
 
-pub async fn handle(request: Request, next: Next) -> Response {
-    let mut cors_headers = HeaderMap::new();
+use crate::library::cfg::CorsConfig;
 
-    cors_headers
-        .insert(ACCESS_CONTROL_ALLOW_ORIGIN, HeaderValue::from_static("*"));
-    cors_headers.insert(
-        ACCESS_CONTROL_ALLOW_CREDENTIALS,
-        HeaderValue::from_static("true"),
-    );
-    cors_headers.insert(
-        ACCESS_CONTROL_ALLOW_METHODS,
-        HeaderValue::from_static("GET, POST, PUT, DELETE, OPTIONS"),
-    );
-    cors_headers.insert(
-        ACCESS_CONTROL_ALLOW_HEADERS,
-        HeaderValue::from_static(
-            "content-type, authorization, withCredentials",
-        ),
-    );
+pub async fn handle(
+    State(cfg): State<Arc<CorsConfig>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let cors_headers = cors_headers(&cfg, request.headers().get(ORIGIN));
 
     if request.method() == Method::OPTIONS {
-        return (StatusCode::NO_CONTENT, cors_headers).into_response();
+        return preflight(request, next, cors_headers).await;
     }
 
     let response = next.run(request).await;
 
     (cors_headers, response).into_response()
 }
+
+/// The CORS headers for a request from `origin`, none unless it's allowed.
+/// Listed origins are reflected, so they may send credentials. Any other
+/// origin allowed by `*` is answered with `*`, which browsers refuse along
+/// with credentials.
+fn cors_headers(cfg: &CorsConfig, origin: Option<&HeaderValue>) -> HeaderMap {
+    let mut cors_headers = HeaderMap::new();
+    let Some(origin) = origin else {
+        return cors_headers;
+    };
+
+    if cfg
+        .allowed_origins
+        .iter()
+        .any(|allowed| *origin == allowed.as_str())
+    {
+        cors_headers.insert(ACCESS_CONTROL_ALLOW_ORIGIN, origin.clone());
+        cors_headers.insert(VARY, HeaderValue::from_static("origin"));
+        if cfg.allow_credentials {
+            cors_headers.insert(
+                ACCESS_CONTROL_ALLOW_CREDENTIALS,
+                HeaderValue::from_static("true"),
+            );
+        }
+    } else if cfg.allowed_origins.iter().any(|allowed| allowed == "*") {
+        cors_headers
+            .insert(ACCESS_CONTROL_ALLOW_ORIGIN, HeaderValue::from_static("*"));
+    } else {
+        return cors_headers;
+    }
+    for (name, values) in [
+        (ACCESS_CONTROL_ALLOW_METHODS, &cfg.allowed_methods),
+        (ACCESS_CONTROL_ALLOW_HEADERS, &cfg.allowed_headers),
+    ] {
+        if let Ok(value) = HeaderValue::from_str(&values.join(", ")) {
+            cors_headers.insert(name, value);
+        }
+    }
+    cors_headers
+}
+
+/// Answers `OPTIONS` for known paths with `204`. No route handles
+/// `OPTIONS`, so the router replies `405`, and fills in `Allow` with the
+/// methods registered for the path once the response leaves the route.
+/// Unknown paths still get a `404`.
+async fn preflight(
+    request: Request,
+    next: Next,
+    cors_headers: HeaderMap,
+) -> Response {
+    let response = next.run(request).await;
+    if response.status() == StatusCode::METHOD_NOT_ALLOWED {
+        return (StatusCode::NO_CONTENT, cors_headers).into_response();
+    }
+    (cors_headers, response).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> CorsConfig {
+        CorsConfig {
+            allowed_origins: vec!["https://app.example.com".to_string()],
+            allowed_methods: vec!["GET".to_string(), "POST".to_string()],
+            allowed_headers: vec!["content-type".to_string()],
+            allow_credentials: true,
+        }
+    }
+
+    #[test]
+    fn test_allowed_origin_is_reflected() {
+        let origin = HeaderValue::from_static("https://app.example.com");
+        let headers = cors_headers(&config(), Some(&origin));
+
+        assert_eq!(headers[ACCESS_CONTROL_ALLOW_ORIGIN], origin);
+        assert_eq!(headers[ACCESS_CONTROL_ALLOW_CREDENTIALS], "true");
+        assert_eq!(headers[ACCESS_CONTROL_ALLOW_METHODS], "GET, POST");
+        assert_eq!(headers[ACCESS_CONTROL_ALLOW_HEADERS], "content-type");
+        assert_eq!(headers[VARY], "origin");
+    }
+
+    #[test]
+    fn test_disallowed_origin_gets_no_headers() {
+        let origin = HeaderValue::from_static("https://evil.example.com");
+        assert!(cors_headers(&config(), Some(&origin)).is_empty());
+        assert!(cors_headers(&config(), None).is_empty());
+
+        let any = CorsConfig {
+            allowed_origins: vec!["*".to_string()],
+            ..config()
+        };
+        let headers = cors_headers(&any, Some(&origin));
+        assert_eq!(headers[ACCESS_CONTROL_ALLOW_ORIGIN], "*");
+        assert!(!headers.contains_key(ACCESS_CONTROL_ALLOW_CREDENTIALS));
+
+        // Listed origins keep their credentials next to `*`.
+        let listed = HeaderValue::from_static("https://app.example.com");
+        let any = CorsConfig {
+            allowed_origins: vec![
+                "*".to_string(),
+                "https://app.example.com".to_string(),
+            ],
+            ..config()
+        };
+        let headers = cors_headers(&any, Some(&listed));
+        assert_eq!(headers[ACCESS_CONTROL_ALLOW_ORIGIN], listed);
+        assert_eq!(headers[ACCESS_CONTROL_ALLOW_CREDENTIALS], "true");
+    }
+
+    #[test]
+    fn test_no_origin_allowed_by_default() {
+        let origin = HeaderValue::from_static("https://app.example.com");
+        assert!(cors_headers(&CorsConfig::default(), Some(&origin)).is_empty());
+    }
+}
@@ -1,39 +1,70 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, time::Instant};
 
 use axum::{
     body::Body,
     extract::Request,
-    http::header::CONTENT_TYPE,
+    http::header::{CONTENT_LENGTH, CONTENT_TYPE},
     middleware::Next,
     response::{IntoResponse, Response},
 };
-use http_body_util::BodyExt;
+use http_body_util::{BodyExt, Limited};
 use hyper::HeaderMap;
+use serde_json::Value;
+use tracing::Level;
 
-use crate::library::error::AppError;
+use super::req_id::RequestId;
+use crate::library::{cfg, error::AppError};
 
+/// Logs every request with its response status and latency, at `debug`
+/// unless the response is a server error.
 pub async fn handle(request: Request, next: Next) -> Response {
-    let enter_time = chrono::Local::now();
+    let started = Instant::now();
     let req_method = request.method().to_string();
     let req_uri = request.uri().to_string();
     let req_header = header_to_string(request.headers());
+    let request_id = request
+        .extensions()
+        .get::<RequestId>()
+        .map(|id| id.0.clone());
 
-    let (response, body) = match drain_body(request, next).await {
-        Err(err) => return err.into_response(),
-        Ok(v) => v,
-    };
+    let (limit, redacted) = cfg::try_config().map_or_else(
+        || {
+            let keys = cfg::DEFAULT_LOG_REDACTED_KEYS;
+            (
+                cfg::DEFAULT_LOG_BODY_LIMIT,
+                keys.iter().map(ToString::to_string).collect(),
+            )
+        },
+        |cfg| (cfg.app.log_body_limit, cfg.app.log_redacted_keys.clone()),
+    );
+    let (response, body) =
+        match drain_body(request, next, limit, &redacted).await {
+            Err(err) => return err.into_response(),
+            Ok(v) => v,
+        };
 
-    let duration = chrono::Local::now()
-        .signed_duration_since(enter_time)
-        .to_string();
+    let status = response.status().as_u16();
+    let latency_ms = started.elapsed().as_millis() as u64;
 
-    tracing::debug!(
-        method = req_method,
-        uri = req_uri,
-        body = body,
-        duration = duration,
-        headers = req_header,
-    );
+    macro_rules! access_log {
+        ($level:expr) => {
+            tracing::event!(
+                $level,
+                request_id = request_id,
+                method = req_method,
+                uri = req_uri,
+                status = status,
+                latency_ms = latency_ms,
+                body = body,
+                headers = req_header,
+            )
+        };
+    }
+    if response.status().is_server_error() {
+        access_log!(Level::INFO);
+    } else {
+        access_log!(Level::DEBUG);
+    }
 
     response
 }
@@ -56,30 +87,106 @@ fn header_to_string(h: &HeaderMap) -> String {
     serde_json::to_string(&map).unwrap_or_else(|_| String::from("<none>"))
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BodyKind {
+    Json,
+    Form,
+}
+
+impl BodyKind {
+    fn of(headers: &HeaderMap) -> Option<Self> {
+        let content_type = headers.get(CONTENT_TYPE)?.to_str().ok()?;
+        if content_type.starts_with("application/json") {
+            Some(Self::Json)
+        } else if content_type.starts_with("application/x-www-form-urlencoded")
+        {
+            Some(Self::Form)
+        } else {
+            None
+        }
+    }
+}
+
+const REDACTED: &str = "***";
+
+/// The body as logged, with the values of `keys` masked. Bodies that can't
+/// be parsed to find them are left out entirely.
+fn redact_body(kind: BodyKind, body: &str, keys: &[String]) -> String {
+    let is_redacted = |key: &str| keys.iter().any(|k| k == key);
+    match kind {
+        BodyKind::Json => {
+            let Ok(mut value) = serde_json::from_str::<Value>(body) else {
+                return "<redacted>".to_string();
+            };
+            redact_json(&mut value, &is_redacted);
+            value.to_string()
+        }
+        BodyKind::Form => {
+            let mut pairs = Vec::new();
+            for pair in body.split('&') {
+                let key = pair.split_once('=').map_or(pair, |(key, _)| key);
+                // Encoded keys could hide a redacted one.
+                if key.contains(['%', '+']) {
+                    return "<redacted>".to_string();
+                }
+                if is_redacted(key) {
+                    pairs.push(format!("{key}={REDACTED}"));
+                } else {
+                    pairs.push(pair.to_string());
+                }
+            }
+            pairs.join("&")
+        }
+    }
+}
+
+fn redact_json(value: &mut Value, is_redacted: &impl Fn(&str) -> bool) {
+    match value {
+        Value::Object(map) => {
+            for (key, value) in map {
+                if is_redacted(key) {
+                    *value = Value::from(REDACTED);
+                } else {
+                    redact_json(value, is_redacted);
+                }
+            }
+        }
+        Value::Array(values) => {
+            for value in values {
+                redact_json(value, is_redacted);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Buffers JSON and form bodies of at most `limit` bytes to log them, with
+/// the values of the `redacted` keys masked. Other bodies, including
+/// streamed ones of unknown size, are passed through untouched, leaving a
+/// hard limit to the routes, e.g. with axum's `DefaultBodyLimit` which
+/// answers `413`.
 async fn drain_body(
     request: Request,
     next: Next,
+    limit: usize,
+    redacted: &[String],
 ) -> Result<(Response, Option<String>), AppError> {
-    let ok = match request
+    let kind = BodyKind::of(request.headers());
+
+    let fits = request
         .headers()
-        .get(CONTENT_TYPE)
-        .and_then(|value| value.to_str().ok())
-    {
-        Some(v) => {
-            v.starts_with("application/json")
-                || v.starts_with("application/x-www-form-urlencoded")
-        }
-        None => false,
-    };
+        .get(CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok()?.parse::<usize>().ok())
+        .is_some_and(|length| length <= limit);
 
-    if !ok {
+    let Some(kind) = kind.filter(|_| fits) else {
         return Ok((next.run(request).await, None));
-    }
+    };
 
     let (parts, body) = request.into_parts();
 
-    // this wont work if the body is an long running stream
-    let bytes = match body.collect().await {
+    // The content length is enforced by hyper, the limit is a safeguard.
+    let bytes = match Limited::new(body, limit).collect().await {
         Ok(v) => v.to_bytes(),
         Err(err) => {
             tracing::error!("err parse request body : {err:?}");
@@ -88,8 +195,8 @@ async fn drain_body(
     };
 
     let body = std::str::from_utf8(&bytes)
-        .map(std::string::ToString::to_string)
-        .ok();
+        .ok()
+        .map(|body| redact_body(kind, body, redacted));
 
     let response = next
         .run(Request::from_parts(parts, Body::from(bytes)))
@@ -97,3 +204,207 @@ async fn drain_body(
 
     Ok((response, body))
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        convert::Infallible,
+        io,
+        pin::Pin,
+        sync::{Arc, Mutex},
+        task::{Context, Poll},
+        time::Duration,
+    };
+
+    use axum::{middleware::from_fn, routing::post, Router};
+    use bytes::Bytes;
+    use hyper::{
+        body::{Body as HttpBody, Frame},
+        StatusCode,
+    };
+    use tower::ServiceExt;
+    use tracing_subscriber::fmt::MakeWriter;
+
+    use super::*;
+    use crate::app::api::middleware::req_id;
+
+    #[derive(Clone, Default)]
+    struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+    impl io::Write for Buffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl<'a> MakeWriter<'a> for Buffer {
+        type Writer = Self;
+
+        fn make_writer(&'a self) -> Self::Writer {
+            self.clone()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_server_errors_logged_with_status_and_id_at_info() {
+        #[allow(clippy::unused_async)]
+        async fn fail() -> StatusCode {
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+        let app = Router::new()
+            .route("/", post(fail))
+            .layer(from_fn(handle))
+            .layer(from_fn(req_id::handle));
+        let buffer = Buffer::default();
+        let subscriber = tracing_subscriber::fmt()
+            .json()
+            .with_max_level(Level::INFO)
+            .with_writer(buffer.clone())
+            .finish();
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        let request = Request::builder()
+            .method("POST")
+            .uri("/")
+            .header(req_id::REQUEST_ID_HEADER, "req-500")
+            .body(Body::empty())
+            .unwrap();
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+
+        let logged =
+            String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        let event: Value = serde_json::from_str(logged.trim()).unwrap();
+        assert_eq!(event["level"], "INFO");
+        assert_eq!(event["fields"]["status"], 500);
+        assert_eq!(event["fields"]["request_id"], "req-500");
+        assert!(event["fields"]["latency_ms"].is_u64());
+    }
+
+    /// A body that never yields, so buffering it would hang.
+    struct Pending;
+
+    impl HttpBody for Pending {
+        type Data = Bytes;
+        type Error = Infallible;
+
+        fn poll_frame(
+            self: Pin<&mut Self>,
+            _cx: &mut Context<'_>,
+        ) -> Poll<Option<Result<Frame<Bytes>, Infallible>>> {
+            Poll::Pending
+        }
+    }
+
+    fn request(length: usize, body: Body) -> Request {
+        Request::builder()
+            .method("POST")
+            .uri("/")
+            .header(CONTENT_TYPE, "application/json")
+            .header(CONTENT_LENGTH, length)
+            .body(body)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_login_password_is_redacted() {
+        let keys = default_keys();
+        let logged = redact_body(
+            BodyKind::Json,
+            r#"{"email_or_name":"VJ","password":"hunter22","remember_me":true}"#,
+            &keys,
+        );
+        assert!(!logged.contains("hunter22"));
+        assert_eq!(
+            serde_json::from_str::<Value>(&logged).unwrap(),
+            serde_json::json!({
+                "email_or_name": "VJ",
+                "password": "***",
+                "remember_me": true,
+            })
+        );
+
+        let logged = redact_body(
+            BodyKind::Json,
+            r#"{"nested":[{"refresh_token":"t"}],"code":"ABC234"}"#,
+            &keys,
+        );
+        assert_eq!(
+            logged,
+            r#"{"code":"***","nested":[{"refresh_token":"***"}]}"#
+        );
+    }
+
+    #[test]
+    fn test_unparsable_bodies_are_redacted() {
+        let keys = default_keys();
+        assert_eq!(
+            redact_body(BodyKind::Json, "{\"password\":", &keys),
+            "<redacted>"
+        );
+        assert_eq!(
+            redact_body(
+                BodyKind::Form,
+                "email_or_name=VJ&password=hunter22",
+                &keys
+            ),
+            "email_or_name=VJ&password=***"
+        );
+        assert_eq!(
+            redact_body(BodyKind::Form, "pass%77ord=hunter22", &keys),
+            "<redacted>"
+        );
+    }
+
+    fn default_keys() -> Vec<String> {
+        cfg::DEFAULT_LOG_REDACTED_KEYS
+            .iter()
+            .map(ToString::to_string)
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_oversized_body_is_not_buffered() {
+        #[allow(clippy::unused_async)]
+        async fn ignore_body() -> &'static str {
+            "ok"
+        }
+        let app = Router::new()
+            .route("/", post(ignore_body))
+            .layer(from_fn(handle));
+
+        let oversized =
+            request(10 * cfg::DEFAULT_LOG_BODY_LIMIT, Body::new(Pending));
+        let response = tokio::time::timeout(
+            Duration::from_secs(1),
+            app.oneshot(oversized),
+        )
+        .await
+        .expect("the body was buffered")
+        .unwrap();
+        assert_eq!(response.status(), hyper::StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_bodies_reach_the_handler_intact() {
+        async fn body_length(body: Bytes) -> String {
+            body.len().to_string()
+        }
+        let app = Router::new()
+            .route("/", post(body_length))
+            .layer(from_fn(handle));
+
+        for length in [16, 2 * cfg::DEFAULT_LOG_BODY_LIMIT] {
+            let body = Body::from(vec![b'a'; length]);
+            let response =
+                app.clone().oneshot(request(length, body)).await.unwrap();
+            let bytes =
+                response.into_body().collect().await.unwrap().to_bytes();
+            assert_eq!(bytes, length.to_string());
+        }
+    }
+}
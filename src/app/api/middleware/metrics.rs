@@ -0,0 +1,325 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    sync::{Mutex, OnceLock, PoisonError},
+    time::Instant,
+};
+
+use axum::{
+    extract::{MatchedPath, Request},
+    middleware::Next,
+    response::Response,
+};
+use serde::Serialize;
+
+/// Upper bounds in seconds of the latency histogram buckets.
+const LATENCY_BUCKETS: [f64; 11] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+/// Route label of requests no route matched, so scanners can't blow up the
+/// number of series.
+const UNMATCHED_ROUTE: &str = "unmatched";
+
+pub const CONTENT_TYPE: &str =
+    "application/openmetrics-text; version=1.0.0; charset=utf-8";
+
+#[derive(Default)]
+struct Histogram {
+    /// Per bucket, not cumulative.
+    buckets: [u64; LATENCY_BUCKETS.len()],
+    count: u64,
+    sum: f64,
+}
+
+impl Histogram {
+    fn observe(&mut self, seconds: f64) {
+        if let Some(i) = LATENCY_BUCKETS.iter().position(|le| seconds <= *le) {
+            self.buckets[i] += 1;
+        }
+        self.count += 1;
+        self.sum += seconds;
+    }
+}
+
+/// Totals of a route since the server started.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RouteSnapshot {
+    pub method: String,
+    pub route: String,
+    pub requests: u64,
+    /// Responses with a 4xx status.
+    pub client_errors: u64,
+    /// Responses with a 5xx status.
+    pub server_errors: u64,
+    /// Share of `requests` answered with a 5xx status.
+    pub error_rate: f64,
+    pub mean_latency_ms: f64,
+}
+
+/// Request counts by method, route and status, and latencies by method and
+/// route.
+#[derive(Default)]
+pub struct Metrics {
+    requests: Mutex<BTreeMap<(String, String, u16), u64>>,
+    latencies: Mutex<BTreeMap<(String, String), Histogram>>,
+}
+
+pub fn metrics() -> &'static Metrics {
+    static METRICS: OnceLock<Metrics> = OnceLock::new();
+    METRICS.get_or_init(Metrics::default)
+}
+
+impl Metrics {
+    fn record(&self, method: &str, route: &str, status: u16, seconds: f64) {
+        *self
+            .requests
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .entry((method.to_string(), route.to_string(), status))
+            .or_default() += 1;
+        self.latencies
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .entry((method.to_string(), route.to_string()))
+            .or_default()
+            .observe(seconds);
+    }
+
+    /// Per route totals, from the counters rendered by [`Metrics::render`].
+    pub fn snapshot(&self) -> Vec<RouteSnapshot> {
+        let mut routes = BTreeMap::<(String, String), RouteSnapshot>::new();
+        let requests =
+            self.requests.lock().unwrap_or_else(PoisonError::into_inner);
+        for ((method, route, status), count) in requests.iter() {
+            let snapshot = routes
+                .entry((method.clone(), route.clone()))
+                .or_insert_with(|| RouteSnapshot {
+                    method: method.clone(),
+                    route: route.clone(),
+                    requests: 0,
+                    client_errors: 0,
+                    server_errors: 0,
+                    error_rate: 0.0,
+                    mean_latency_ms: 0.0,
+                });
+            snapshot.requests += count;
+            match status {
+                400..=499 => snapshot.client_errors += count,
+                500..=599 => snapshot.server_errors += count,
+                _ => {}
+            }
+        }
+        drop(requests);
+
+        let latencies = self
+            .latencies
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        for (key, snapshot) in &mut routes {
+            snapshot.error_rate =
+                snapshot.server_errors as f64 / snapshot.requests as f64;
+            if let Some(histogram) =
+                latencies.get(key).filter(|histogram| histogram.count > 0)
+            {
+                snapshot.mean_latency_ms =
+                    histogram.sum * 1000.0 / histogram.count as f64;
+            }
+        }
+        routes.into_values().collect()
+    }
+
+    /// Appends the request counter and the latency histogram to `out`, in
+    /// OpenMetrics text format.
+    pub fn render(&self, out: &mut String) {
+        let requests =
+            self.requests.lock().unwrap_or_else(PoisonError::into_inner);
+        out.push_str("# TYPE http_requests counter\n");
+        out.push_str("# HELP http_requests Requests handled.\n");
+        for ((method, route, status), count) in requests.iter() {
+            let _ = writeln!(
+                out,
+                "http_requests_total{{method=\"{}\",route=\"{}\",status=\"{status}\"}} {count}",
+                escape(method),
+                escape(route),
+            );
+        }
+        drop(requests);
+
+        let latencies = self
+            .latencies
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        out.push_str("# TYPE http_request_duration_seconds histogram\n");
+        out.push_str("# HELP http_request_duration_seconds Request latency.\n");
+        for ((method, route), histogram) in latencies.iter() {
+            let labels = format!(
+                "method=\"{}\",route=\"{}\"",
+                escape(method),
+                escape(route)
+            );
+            let mut cumulative = 0;
+            for (le, count) in LATENCY_BUCKETS.iter().zip(histogram.buckets) {
+                cumulative += count;
+                let _ = writeln!(
+                    out,
+                    "http_request_duration_seconds_bucket{{{labels},le=\"{le}\"}} {cumulative}"
+                );
+            }
+            let _ = writeln!(
+                out,
+                "http_request_duration_seconds_bucket{{{labels},le=\"+Inf\"}} {}",
+                histogram.count
+            );
+            let _ = writeln!(
+                out,
+                "http_request_duration_seconds_sum{{{labels}}} {}",
+                histogram.sum
+            );
+            let _ = writeln!(
+                out,
+                "http_request_duration_seconds_count{{{labels}}} {}",
+                histogram.count
+            );
+        }
+    }
+}
+
+/// Appends a gauge with a sample per label set to `out`, in OpenMetrics
+/// text format.
+pub fn render_gauge(
+    out: &mut String,
+    name: &str,
+    help: &str,
+    samples: &[(&str, u64)],
+) {
+    let _ = writeln!(out, "# TYPE {name} gauge\n# HELP {name} {help}");
+    for (labels, value) in samples {
+        if labels.is_empty() {
+            let _ = writeln!(out, "{name} {value}");
+        } else {
+            let _ = writeln!(out, "{name}{{{labels}}} {value}");
+        }
+    }
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', r"\\")
+        .replace('"', "\\\"")
+        .replace('\n', r"\n")
+}
+
+pub async fn handle(request: Request, next: Next) -> Response {
+    let start = Instant::now();
+    let method = request.method().to_string();
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map_or(UNMATCHED_ROUTE, MatchedPath::as_str)
+        .to_string();
+
+    let response = next.run(request).await;
+    metrics().record(
+        &method,
+        &route,
+        response.status().as_u16(),
+        start.elapsed().as_secs_f64(),
+    );
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{
+        body::Body,
+        http::{Request, StatusCode},
+        middleware::from_fn,
+        routing::get,
+        Router,
+    };
+    use tower::ServiceExt;
+
+    use super::*;
+
+    #[allow(clippy::unused_async)]
+    async fn ok_handler() -> StatusCode {
+        StatusCode::OK
+    }
+
+    fn rendered() -> String {
+        let mut out = String::new();
+        metrics().render(&mut out);
+        out
+    }
+
+    #[tokio::test]
+    async fn test_request_counted() {
+        let app = Router::new()
+            .route("/metrics_test/:id", get(ok_handler))
+            .layer(from_fn(handle));
+        let line = "http_requests_total{method=\"GET\",route=\"/metrics_test/:id\",status=\"200\"}";
+        assert!(!rendered().contains(line));
+
+        for id in [1, 2] {
+            let request = Request::builder()
+                .uri(format!("/metrics_test/{id}"))
+                .body(Body::empty())
+                .unwrap();
+            app.clone().oneshot(request).await.unwrap();
+        }
+
+        let out = rendered();
+        assert!(out.contains(&format!("{line} 2\n")), "{out}");
+        assert!(out.contains(
+            "http_request_duration_seconds_count{method=\"GET\",route=\"/metrics_test/:id\"} 2\n"
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_snapshot_reflects_request() {
+        let app = Router::new()
+            .route("/snapshot_test", get(ok_handler))
+            .route(
+                "/snapshot_test/fail",
+                get(|| async { StatusCode::INTERNAL_SERVER_ERROR }),
+            )
+            .layer(from_fn(handle));
+        for uri in ["/snapshot_test", "/snapshot_test/fail"] {
+            let request =
+                Request::builder().uri(uri).body(Body::empty()).unwrap();
+            app.clone().oneshot(request).await.unwrap();
+        }
+
+        let snapshot = metrics().snapshot();
+        let route = |route: &str| {
+            snapshot.iter().find(|s| s.route == route).cloned().unwrap()
+        };
+        let ok = route("/snapshot_test");
+        assert_eq!((ok.method.as_str(), ok.requests), ("GET", 1));
+        assert_eq!(ok.error_rate, 0.0);
+        let fail = route("/snapshot_test/fail");
+        assert_eq!((fail.requests, fail.server_errors), (1, 1));
+        assert_eq!(fail.error_rate, 1.0);
+    }
+
+    #[test]
+    fn test_histogram_buckets_cumulative() {
+        let metrics = Metrics::default();
+        metrics.record("GET", "/h", 200, 0.003);
+        metrics.record("GET", "/h", 500, 0.3);
+        let mut out = String::new();
+        metrics.render(&mut out);
+
+        for (le, count) in [("0.005", 1), ("0.25", 1), ("0.5", 2), ("+Inf", 2)]
+        {
+            let line = format!(
+                "http_request_duration_seconds_bucket{{method=\"GET\",route=\"/h\",le=\"{le}\"}} {count}\n"
+            );
+            assert!(out.contains(&line), "{line} missing in {out}");
+        }
+        assert!(out.contains(
+            "http_requests_total{method=\"GET\",route=\"/h\",status=\"500\"} 1\n"
+        ));
+    }
+}
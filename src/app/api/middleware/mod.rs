@@ -1,4 +1,9 @@
+pub mod audit;
 pub mod auth;
+pub mod compression;
 pub mod cors;
 pub mod log;
+pub mod metrics;
+pub mod rate_limit;
 pub mod req_id;
+pub mod timeout;
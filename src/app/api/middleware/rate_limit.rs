@@ -0,0 +1,149 @@
+use std::{
+    net::SocketAddr,
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use axum::{
+    extract::{ConnectInfo, MatchedPath, Request, State},
+    middleware::Next,
+    response::Response,
+};
+
+use crate::{
+    app::bootstrap::AppState,
+    library::{
+        cfg::{self, RateLimitConfig},
+        error::{ApiInnerError, AppError::ApiError, AppResult},
+    },
+};
+
+/// Which configured limit of `app.rate_limit` a route is subject to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RateLimitGroup {
+    Lookup,
+    Auth,
+}
+
+impl RateLimitGroup {
+    const fn name(self) -> &'static str {
+        match self {
+            Self::Lookup => "lookup",
+            Self::Auth => "auth",
+        }
+    }
+
+    fn limit(self) -> RateLimitConfig {
+        let cfg = cfg::config();
+        match self {
+            Self::Lookup => cfg.app.rate_limit.lookup,
+            Self::Auth => cfg.app.rate_limit.auth,
+        }
+    }
+}
+
+/// The fixed window of `limit` that `now` (seconds since the epoch) falls
+/// in, aligned to the clock so every instance agrees on it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Window {
+    index: u64,
+    /// Seconds until the window ends, at least 1.
+    remaining: u64,
+}
+
+impl Window {
+    const fn at(limit: RateLimitConfig, now: u64) -> Self {
+        let window = if limit.window == 0 { 1 } else { limit.window };
+        Self {
+            index: now / window,
+            remaining: window - now % window,
+        }
+    }
+
+    /// Whether the `count`th request in this window is admitted, otherwise
+    /// asks to retry in the next one.
+    fn admit(self, limit: RateLimitConfig, count: i64) -> AppResult<()> {
+        if count > limit.requests {
+            return Err(ApiError(ApiInnerError::TooManyRequests {
+                retry_after_secs: self.remaining,
+            }));
+        }
+        Ok(())
+    }
+}
+
+/// Counts requests per client IP and route in a fixed window, rejecting
+/// those beyond the group's limit with a `Retry-After` for the next window.
+pub async fn handle(
+    State(state): State<Arc<AppState>>,
+    request: Request,
+    next: Next,
+    group: RateLimitGroup,
+) -> AppResult<Response> {
+    let ip = request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map_or_else(|| "unknown".to_string(), |info| info.0.ip().to_string());
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map_or_else(|| request.uri().path(), MatchedPath::as_str);
+
+    let limit = group.limit();
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let window = Window::at(limit, now);
+    let key = format!(
+        "rate_limit:{}:{}:{}:{}",
+        group.name(),
+        route,
+        ip,
+        window.index
+    );
+
+    let count = state
+        .get_redis()
+        .await?
+        .incr_ex(&key, 1, Duration::from_secs(window.remaining))
+        .await?;
+    window.admit(limit, count)?;
+
+    Ok(next.run(request).await)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    #[test]
+    fn test_fixed_window() {
+        let limit = RateLimitConfig {
+            requests: 2,
+            window: 60,
+        };
+        let mut counters = HashMap::<u64, i64>::new();
+        let mut request = |now: u64| {
+            let window = Window::at(limit, now);
+            let count = counters.entry(window.index).or_default();
+            *count += 1;
+            // The retry hint of a rejection.
+            window.admit(limit, *count).err().map(|e| match e {
+                ApiError(ApiInnerError::TooManyRequests {
+                    retry_after_secs,
+                }) => retry_after_secs,
+                e => panic!("unexpected error: {e}"),
+            })
+        };
+
+        assert_eq!(request(120), None);
+        assert_eq!(request(150), None);
+        assert_eq!(request(170), Some(10));
+        assert_eq!(request(179), Some(1));
+        // A new window starts from zero.
+        assert_eq!(request(180), None);
+    }
+}
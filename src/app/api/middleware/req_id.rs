@@ -7,19 +7,103 @@ use axum::{
 use http::HeaderName;
 use ulid::Ulid;
 
+pub const REQUEST_ID_HEADER: HeaderName =
+    HeaderName::from_static("x-request-id");
+
+/// Longest client supplied id that is kept, longer ones are replaced.
+const MAX_REQUEST_ID_LEN: usize = 128;
+
+/// The id of the request, in the request extensions for the inner
+/// middlewares and handlers.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RequestId(pub String);
+
+/// Tags the request and its response with an `x-request-id`, the client's
+/// own when it sent a usable one.
 pub async fn handle(mut request: Request, next: Next) -> Response {
-    let req_id = HeaderValue::from_str(&Ulid::new().to_string())
-        .unwrap_or(HeaderValue::from_static("unknown"));
+    let req_id = request
+        .headers()
+        .get(&REQUEST_ID_HEADER)
+        .filter(|value| is_usable(value))
+        .cloned()
+        .unwrap_or_else(|| {
+            HeaderValue::from_str(&Ulid::new().to_string())
+                .unwrap_or(HeaderValue::from_static("unknown"))
+        });
 
     request
         .headers_mut()
-        .insert(HeaderName::from_static("x-request-id"), req_id.clone());
+        .insert(REQUEST_ID_HEADER, req_id.clone());
+    if let Ok(id) = req_id.to_str() {
+        request.extensions_mut().insert(RequestId(id.to_string()));
+    }
 
     let mut response = next.run(request).await;
 
-    response
-        .headers_mut()
-        .insert(HeaderName::from_static("x-request-id"), req_id);
+    response.headers_mut().insert(REQUEST_ID_HEADER, req_id);
 
     response
 }
+
+fn is_usable(value: &HeaderValue) -> bool {
+    value.to_str().is_ok_and(|id| {
+        !id.is_empty()
+            && id.len() <= MAX_REQUEST_ID_LEN
+            && id.bytes().all(|b| b.is_ascii_graphic())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{
+        body::Body, middleware::from_fn, routing::get, Extension, Router,
+    };
+    use tower::ServiceExt;
+
+    use super::*;
+
+    fn app() -> Router {
+        Router::new()
+            .route(
+                "/",
+                get(|Extension(id): Extension<RequestId>| async move { id.0 }),
+            )
+            .layer(from_fn(handle))
+    }
+
+    #[tokio::test]
+    async fn test_client_request_id_is_echoed() {
+        let request = Request::builder()
+            .uri("/")
+            .header(REQUEST_ID_HEADER, "client-id-1")
+            .body(Body::empty())
+            .unwrap();
+        let response = app().oneshot(request).await.unwrap();
+        assert_eq!(response.headers()[REQUEST_ID_HEADER], "client-id-1");
+    }
+
+    #[tokio::test]
+    async fn test_request_id_generated_when_missing_or_unusable() {
+        for sent in [None, Some("x".repeat(MAX_REQUEST_ID_LEN + 1))] {
+            let mut request = Request::builder().uri("/");
+            if let Some(sent) = &sent {
+                request = request.header(REQUEST_ID_HEADER, sent);
+            }
+            let response = app()
+                .oneshot(request.body(Body::empty()).unwrap())
+                .await
+                .unwrap();
+            let id = response.headers()[REQUEST_ID_HEADER]
+                .to_str()
+                .unwrap()
+                .to_string();
+            assert!(id.parse::<Ulid>().is_ok());
+            // Handlers see the same id.
+            let body = http_body_util::BodyExt::collect(response.into_body())
+                .await
+                .unwrap()
+                .to_bytes();
+            assert_eq!(body, id);
+        }
+    }
+}
@@ -0,0 +1,18 @@
+use std::time::Duration;
+
+use axum::{
+    extract::Request,
+    http::StatusCode,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+
+use crate::library::cfg;
+
+// The timeout is read per request so it follows config reloads.
+pub async fn handle(request: Request, next: Next) -> Response {
+    let timeout = Duration::from_secs(cfg::config().app.request_timeout);
+    tokio::time::timeout(timeout, next.run(request))
+        .await
+        .unwrap_or_else(|_| StatusCode::REQUEST_TIMEOUT.into_response())
+}
@@ -1,4 +1,4 @@
-use std::sync::Arc;
+use std::{net::SocketAddr, sync::Arc};
 
 use tokio::net::TcpListener;
 
@@ -8,11 +8,12 @@ use crate::{
 };
 
 pub mod controller;
+pub mod extractor;
 pub mod middleware;
 pub mod route;
 
 pub struct Server {
-    pub host: &'static str,
+    pub host: String,
     pub port: usize,
     pub app_state: Arc<AppState>,
 }
@@ -20,7 +21,7 @@ pub struct Server {
 impl Server {
     pub fn init(app_state: Arc<AppState>) -> Self {
         let config = cfg::config();
-        let host = &config.app.host;
+        let host = config.app.host.clone();
         let port = config.app.port;
         Self {
             host,
@@ -46,9 +47,12 @@ impl Server {
         );
 
         // Run the server with graceful shutdown
-        axum::serve(listener, app)
-            .with_graceful_shutdown(shutdown_signal())
-            .await
-            .unwrap_or_else(|e| panic!("💥 Failed to start API server: {e:?}"));
+        axum::serve(
+            listener,
+            app.into_make_service_with_connect_info::<SocketAddr>(),
+        )
+        .with_graceful_shutdown(shutdown_signal(self.app_state.clone()))
+        .await
+        .unwrap_or_else(|e| panic!("💥 Failed to start API server: {e:?}"));
     }
 }
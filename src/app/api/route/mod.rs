@@ -1,69 +1,316 @@
-use std::{sync::Arc, time::Duration};
+use std::sync::Arc;
 
 use axum::{
     middleware::{from_fn, from_fn_with_state},
-    routing::post,
+    routing::{get, post, MethodRouter},
     Router,
 };
-use tower_http::timeout::TimeoutLayer;
 
 use super::{
     controller::{
         common::handler_404,
+        health::{health_handler, ready_handler},
+        metrics::metrics_handler,
         v1::account::{
-            change_password_handler, refresh_token_handler,
-            send_reset_password_email_handler,
-            verify_active_account_code_handler,
+            activate_by_link_handler, change_password_handler,
+            delete_me_handler, invalidate_sessions_handler, logout_handler,
+            refresh_token_handler, send_reset_password_email_handler,
+            update_profile_handler, verify_active_account_code_handler,
         },
     },
-    middleware::{auth, cors, log, req_id},
+    middleware::{
+        audit::{self, AuditSink},
+        auth::{self, AuthRequirement},
+        compression, cors, log, metrics,
+        rate_limit::{self, RateLimitGroup},
+        req_id, timeout,
+    },
 };
-use crate::app::{
-    api::controller::v1::account::{
-        get_me_handler, login_user_handler, register_user_handler,
-        send_active_account_email_handler,
+use crate::{
+    app::{
+        api::controller::v1::{
+            account::{
+                email_available_handler, get_me_handler, login_user_handler,
+                register_user_handler, send_active_account_email_handler,
+            },
+            admin::{
+                list_accounts_handler, requeue_email_dlq_handler,
+                stats_handler, suspend_accounts_handler,
+            },
+        },
+        bootstrap::AppState,
     },
-    bootstrap::AppState,
+    library::cfg,
 };
 
-pub fn init(app_state: Arc<AppState>) -> Router {
-    let open = Router::new()
-        .route("/auth/login", post(login_user_handler))
-        .route("/auth/register", post(register_user_handler))
-        .route("/auth/refresh_token", post(refresh_token_handler));
+/// A router that requires every route to declare its `AuthRequirement`,
+/// enforced by `auth::handle` on that route alone.
+pub struct AuthRouter<S = ()> {
+    router: Router<S>,
+    revocations: Option<Arc<AppState>>,
+}
+
+impl<S> AuthRouter<S>
+where
+    S: Clone + Send + Sync + 'static,
+{
+    pub fn new() -> Self {
+        Self {
+            router: Router::new(),
+            revocations: None,
+        }
+    }
+
+    /// Also rejects revoked tokens on the routes added afterwards.
+    #[must_use]
+    pub fn check_revocations(self, state: Arc<AppState>) -> Self {
+        Self {
+            revocations: Some(state),
+            ..self
+        }
+    }
+
+    #[must_use]
+    pub fn route(
+        self,
+        path: &str,
+        requirement: AuthRequirement,
+        method_router: MethodRouter<S>,
+    ) -> Self {
+        let state = self.revocations.clone();
+        Self {
+            router: self.router.route(
+                path,
+                method_router.route_layer(from_fn(move |req, next| {
+                    auth::handle(req, next, requirement, state.clone())
+                })),
+            ),
+            ..self
+        }
+    }
 
-    let basic = Router::new()
+    pub fn into_router(self) -> Router<S> {
+        self.router
+    }
+}
+
+impl<S> Default for AuthRouter<S>
+where
+    S: Clone + Send + Sync + 'static,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub fn init(app_state: Arc<AppState>) -> Router {
+    let v1 = AuthRouter::new()
+        .check_revocations(app_state.clone())
+        .route(
+            "/auth/login",
+            AuthRequirement::Open,
+            post(login_user_handler).route_layer(from_fn_with_state(
+                app_state.clone(),
+                |state, req, next| {
+                    rate_limit::handle(state, req, next, RateLimitGroup::Auth)
+                },
+            )),
+        )
+        .route(
+            "/auth/activate",
+            AuthRequirement::Open,
+            post(activate_by_link_handler).route_layer(from_fn_with_state(
+                app_state.clone(),
+                |state, req, next| {
+                    rate_limit::handle(state, req, next, RateLimitGroup::Auth)
+                },
+            )),
+        )
+        .route(
+            "/auth/register",
+            AuthRequirement::Open,
+            post(register_user_handler).route_layer(from_fn_with_state(
+                app_state.clone(),
+                |state, req, next| {
+                    rate_limit::handle(state, req, next, RateLimitGroup::Auth)
+                },
+            )),
+        )
+        .route(
+            "/auth/email_available",
+            AuthRequirement::Open,
+            get(email_available_handler).route_layer(from_fn_with_state(
+                app_state.clone(),
+                |state, req, next| {
+                    rate_limit::handle(state, req, next, RateLimitGroup::Lookup)
+                },
+            )),
+        )
+        .route(
+            "/auth/refresh_token",
+            AuthRequirement::Open,
+            post(refresh_token_handler),
+        )
+        .route(
+            "/auth/logout",
+            AuthRequirement::Authenticated,
+            post(logout_handler),
+        )
+        .route(
+            "/auth/invalidate_sessions",
+            AuthRequirement::Authenticated,
+            post(invalidate_sessions_handler),
+        )
+        .route(
+            "/users/delete_me",
+            AuthRequirement::Authenticated,
+            post(delete_me_handler),
+        )
+        .route(
+            "/users/update_profile",
+            AuthRequirement::Authenticated,
+            post(update_profile_handler),
+        )
         .route(
             "/users/send_active",
+            AuthRequirement::Authenticated,
             post(send_active_account_email_handler),
         )
         .route(
             "/users/verify_active",
+            AuthRequirement::Authenticated,
             post(verify_active_account_code_handler),
         )
-        .layer(from_fn(|req, next| auth::handle(req, next, false)));
-
-    let auth = Router::new()
-        .route("/users/get_me", post(get_me_handler))
+        .route(
+            "/users/get_me",
+            AuthRequirement::Active,
+            post(get_me_handler),
+        )
         .route(
             "/users/send_reset_password",
+            AuthRequirement::Active,
             post(send_reset_password_email_handler),
         )
         .route(
             "/users/verify_reset_password",
+            AuthRequirement::Active,
             post(change_password_handler),
         )
-        .route_layer(from_fn_with_state(app_state.clone(), |req, next| {
-            auth::handle(req, next, true)
-        }))
-        .with_state(app_state.clone());
+        .route(
+            "/admin/accounts",
+            AuthRequirement::Admin,
+            get(list_accounts_handler),
+        )
+        .route(
+            "/admin/accounts/suspend",
+            AuthRequirement::Admin,
+            post(suspend_accounts_handler),
+        )
+        .route("/admin/stats", AuthRequirement::Admin, get(stats_handler))
+        .route(
+            "/admin/dlq/email/requeue",
+            AuthRequirement::Admin,
+            post(requeue_email_dlq_handler),
+        )
+        .into_router();
 
-    Router::new()
-        .nest("/api/v1", open.merge(basic).merge(auth))
+    // Probes, left outside the auth layers.
+    let router = Router::new()
+        .route("/health", get(health_handler))
+        .route("/ready", get(ready_handler))
+        .route("/metrics", get(metrics_handler))
+        .nest("/api/v1", v1)
         .fallback(handler_404)
         .with_state(app_state)
-        .layer(TimeoutLayer::new(Duration::from_secs(30)))
-        .layer(from_fn(log::handle))
-        .layer(from_fn(cors::handle))
+        .layer(from_fn(timeout::handle))
+        .layer(from_fn(metrics::handle))
+        .layer(compression::layer(&cfg::config().app.compression))
+        .layer(from_fn(log::handle));
+
+    let audit_sink = AuditSink::from_config(&cfg::config().app.audit)
+        .unwrap_or_else(|e| panic!("💥 Failed to open the audit file: {e}"));
+    let router = match audit_sink {
+        Some(sink) => {
+            router.layer(from_fn_with_state(Arc::new(sink), audit::handle))
+        }
+        None => router,
+    };
+
+    router
+        .layer(from_fn_with_state(
+            Arc::new(cfg::config().app.cors.clone()),
+            cors::handle,
+        ))
         .layer(from_fn(req_id::handle))
 }
+
+#[cfg(test)]
+mod tests {
+    use axum::{
+        body::Body,
+        http::{Request, StatusCode},
+    };
+    use tower::ServiceExt;
+
+    use super::*;
+
+    #[allow(clippy::unused_async)]
+    async fn ok_handler() -> StatusCode {
+        StatusCode::OK
+    }
+
+    #[tokio::test]
+    async fn test_auth_required_route_rejects_missing_token() {
+        let app = AuthRouter::new()
+            .route("/open", AuthRequirement::Open, post(ok_handler))
+            .route("/active", AuthRequirement::Active, post(ok_handler))
+            .into_router();
+
+        let request = |uri| {
+            Request::builder()
+                .method("POST")
+                .uri(uri)
+                .body(Body::empty())
+                .unwrap()
+        };
+
+        let response = app.clone().oneshot(request("/open")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let response = app.oneshot(request("/active")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn test_options_returns_allowed_methods() {
+        let v1 = AuthRouter::new()
+            .route("/users/get_me", AuthRequirement::Active, post(ok_handler))
+            .into_router();
+        let app = Router::new()
+            .nest("/api/v1", v1)
+            .fallback(handler_404)
+            .layer(from_fn_with_state(
+                Arc::new(cfg::CorsConfig::default()),
+                cors::handle,
+            ));
+
+        let request = |uri| {
+            Request::builder()
+                .method("OPTIONS")
+                .uri(uri)
+                .body(Body::empty())
+                .unwrap()
+        };
+
+        let response = app
+            .clone()
+            .oneshot(request("/api/v1/users/get_me"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        assert_eq!(response.headers()["allow"], "POST");
+
+        let response = app.oneshot(request("/api/v1/unknown")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+}
@@ -1,7 +1,29 @@
-pub const MQ_SEND_EMAIL_QUEUE: &str = "app.dev.send_email";
+/// Declared with a dead-letter exchange. RabbitMQ refuses to redeclare a queue
+/// with different arguments, so this replaces `app.dev.send_email`, declared
+/// without one. Nothing consumes the old queue after an upgrade, so shovel
+/// whatever is left in it here before deleting it.
+pub const MQ_SEND_EMAIL_QUEUE: &str = "app.dev.send_email.v2";
 
 pub const MQ_SEND_EMAIL_TAG: &str = "app.dev.send_email_tag";
 
+pub const MQ_SEND_EMAIL_DLX: &str = "app.dev.send_email.v2.dlx";
+
+pub const MQ_EVENT_EXCHANGE: &str = "app.dev.event";
+
+pub const MQ_WELCOME_EMAIL_QUEUE: &str = "app.dev.welcome_email";
+
+pub const MQ_WELCOME_EMAIL_TAG: &str = "app.dev.welcome_email_tag";
+
 pub const REDIS_ACTIVE_ACCOUNT_KEY: &str = "active_code";
 
 pub const REDIS_RESET_PASSWORD_KEY: &str = "reset_password_code";
+
+pub const REDIS_NAME_CHANGE_KEY: &str = "name_change";
+
+pub const REDIS_ADMIN_ACTIONS_KEY: &str = "admin_actions";
+
+pub const REDIS_REVOKED_TOKEN_KEY: &str = "revoked_token";
+
+pub const REDIS_TOKEN_EPOCH_KEY: &str = "token_epoch";
+
+pub const REDIS_REFRESH_TOKEN_KEY: &str = "refresh_token";
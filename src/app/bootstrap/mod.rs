@@ -1,12 +1,16 @@
 pub mod constants;
 
-use std::sync::Arc;
+use std::{sync::Arc, time::Duration};
 
 use tokio::signal;
 
 use crate::{
-    app::service::Services,
-    library::{dber::DB, error::AppResult, Dber, Mqer, Redis, Redisor},
+    app::{entity::event::Event, service::Services},
+    library::{
+        cfg, dber::DB, error::AppResult, logger, mailor::Email, Dber, Mqer,
+        Redis, Redisor,
+    },
+    models,
 };
 
 pub struct AppState {
@@ -17,8 +21,10 @@ pub struct AppState {
 
 impl AppState {
     pub async fn init() -> Self {
+        let db = Dber::init().await;
+        db.self_check(models::SCHEMA).await;
         Self {
-            db: Dber::init().await,
+            db,
             redis: Redisor::init(),
             services: Services::init().await,
         }
@@ -28,8 +34,14 @@ impl AppState {
         self.services.clone().serve(self).await;
     }
 
-    pub const fn get_db(&self) -> &DB {
-        &self.db.pool
+    /// The primary, for writes and reads that must see them.
+    pub const fn get_write_db(&self) -> &DB {
+        &self.db.write_pool
+    }
+
+    /// The read replica if configured, which may lag behind the primary.
+    pub fn get_read_db(&self) -> &DB {
+        self.db.read_pool()
     }
 
     pub async fn get_redis(&self) -> AppResult<Redis> {
@@ -39,10 +51,32 @@ impl AppState {
     pub fn get_mq(&self) -> AppResult<Arc<Mqer>> {
         Ok(self.services.message_queue.mqer.clone())
     }
+
+    /// Publishes `event` to the event exchange, a no-op unless
+    /// `app.publish_events` is set.
+    pub async fn publish_event<E: Event>(&self, event: &E) -> AppResult<()> {
+        if !cfg::config().app.publish_events {
+            return Ok(());
+        }
+        let payload = serde_json::to_string(event).map_err(|e| {
+            anyhow::anyhow!("Error occurred while publishing event: {}", e)
+        })?;
+        self.get_mq()?
+            .topic_send(constants::MQ_EVENT_EXCHANGE, E::ROUTING_KEY, &payload)
+            .await?;
+        Ok(())
+    }
+
+    /// Queues `email` for the email consumer, see
+    /// `message_queue::Server::enqueue_email`.
+    pub async fn enqueue_email(&self, email: &Email<'_>) -> AppResult<()> {
+        self.services.message_queue.enqueue_email(email).await
+    }
 }
 
-// pub async fn shutdown_signal(app_state: Arc<AppState>) {
-pub async fn shutdown_signal() {
+/// Waits for Ctrl+C or SIGTERM, then stops the services, letting the MQ
+/// consumers drain the deliveries in flight.
+pub async fn shutdown_signal(app_state: Arc<AppState>) {
     let ctrl_c = async {
         signal::ctrl_c()
             .await
@@ -68,5 +102,103 @@ pub async fn shutdown_signal() {
             tracing::info!("Terminate signal received.");
         },
     }
-    // app_state.services.shutdown().await;
+    app_state.services.shutdown().await;
+}
+
+/// Logs the database pools' status every `app.db_pool.monitor_interval_secs`,
+/// see [`Dber::monitor`].
+pub async fn db_monitor(app_state: Arc<AppState>) {
+    let pool = cfg::config().app.db_pool;
+    if pool.monitor_interval_secs == 0 {
+        return;
+    }
+    app_state
+        .db
+        .monitor(
+            Duration::from_secs(pool.monitor_interval_secs),
+            pool.slow_acquire(),
+        )
+        .await;
+}
+
+/// Reloads the hot-reloadable configuration fields on every SIGHUP.
+#[cfg(unix)]
+pub async fn reload_signal() {
+    let mut hangup = signal::unix::signal(signal::unix::SignalKind::hangup())
+        .expect("failed to install SIGHUP handler");
+
+    while hangup.recv().await.is_some() {
+        tracing::info!("Hangup signal received.");
+        let Some(cfg_file) = cfg::config_file() else {
+            continue;
+        };
+        match cfg::reload(&cfg_file.to_string_lossy()) {
+            Ok(()) => logger::reload(&cfg::config()),
+            Err(e) => {
+                tracing::error!("Failed to reload configuration: {e}");
+            }
+        }
+    }
+}
+
+#[cfg(not(unix))]
+pub async fn reload_signal() {
+    std::future::pending::<()>().await;
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use super::*;
+    use crate::library::{error::AppError, mqer::Subscriber};
+
+    #[tokio::test]
+    #[ignore]
+    async fn test_enqueue_email() {
+        cfg::init("./fixtures/config.toml");
+        let state = AppState::init().await;
+        let mqer = state.get_mq().unwrap();
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let sink = received.clone();
+        let delegate = Subscriber::new(
+            move |message| {
+                sink.lock().unwrap().push(message);
+                Ok(())
+            },
+            mqer.clone(),
+        );
+        mqer.basic_receive_with_dlq(
+            constants::MQ_SEND_EMAIL_QUEUE,
+            constants::MQ_SEND_EMAIL_DLX,
+            "app.dev.enqueue_email_tag",
+            delegate,
+        )
+        .await
+        .unwrap();
+
+        let subject = uuid::Uuid::new_v4().to_string();
+        state
+            .enqueue_email(&Email::new("test@test.com", &subject, "body"))
+            .await
+            .unwrap();
+        tokio::time::sleep(Duration::from_secs(1)).await;
+
+        let received = received.lock().unwrap().clone();
+        let email = received
+            .iter()
+            .map(|message| serde_json::from_str::<Email>(message).unwrap())
+            .find(|email| email.subject == subject)
+            .unwrap();
+        assert_eq!(email.to, "test@test.com");
+        assert_eq!(email.body, "body");
+
+        let long = "x".repeat(cfg::config().mail.max_subject_length + 1);
+        assert!(matches!(
+            state
+                .enqueue_email(&Email::new("test@test.com", &long, "body"))
+                .await,
+            Err(AppError::ApiError(_))
+        ));
+    }
 }
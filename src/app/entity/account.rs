@@ -1,7 +1,10 @@
 use serde::{Deserialize, Serialize};
+use sqlx::types::chrono::NaiveDateTime;
+use validator::Validate;
 
 use crate::{
     app::service::jwt_service::TokenSchema,
+    library::validation::validate_name,
     models::{
         account::Account,
         types::{AccountStatus, Language},
@@ -32,24 +35,83 @@ pub struct TokenResponse {
     pub tokens: TokenSchema,
 }
 
+/// The tokens and state of a freshly activated user.
+#[derive(Debug, Serialize)]
+pub struct ActivationResponse {
+    pub tokens: TokenSchema,
+    pub user: UserResponse,
+}
+
 #[derive(Debug, Serialize)]
 pub struct UserResponse {
     pub email: String,
     pub language: Language,
     pub status: AccountStatus,
+    /// `null` before the first login.
+    pub last_login_at: Option<NaiveDateTime>,
 }
 
-#[derive(Debug, Deserialize)]
+impl From<Account> for UserResponse {
+    fn from(user: Account) -> Self {
+        Self {
+            email: user.email,
+            language: user.language,
+            status: user.status,
+            last_login_at: user.last_login_at,
+        }
+    }
+}
+
+impl UserResponse {
+    /// Fields selectable through
+    /// [`FieldsQuery`](crate::app::entity::common::FieldsQuery).
+    pub const FIELDS: &'static [&'static str] =
+        &["email", "language", "status", "last_login_at"];
+}
+
+#[derive(Debug, Deserialize, Validate)]
 pub struct RegisterUserRequest {
+    #[validate(custom(function = "validate_name"))]
     pub name: String,
+    #[validate(email, length(max = 255))]
     pub email: String,
+    #[validate(length(min = 8, max = 128))]
     pub password: String,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Validate)]
 pub struct LoginUserRequest {
+    // Missing fields are treated as blank and rejected as wrong credentials,
+    // so only overlong ones fail validation.
+    #[serde(default)]
+    #[validate(length(max = 255))]
     pub email_or_name: String,
+    #[serde(default)]
+    #[validate(length(max = 128))]
     pub password: String,
+    #[serde(default)]
+    pub remember_me: bool,
+    /// Stable id of the client's device, the session is bound to.
+    #[validate(length(max = 255))]
+    pub device_id: Option<String>,
+}
+
+/// Fields left out are kept as they are.
+#[derive(Debug, Deserialize, Validate)]
+pub struct UpdateProfileRequest {
+    #[validate(custom(function = "validate_name"))]
+    pub name: Option<String>,
+    pub language: Option<Language>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct EmailAvailableQuery {
+    pub email: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct EmailAvailableResponse {
+    pub available: bool,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -63,6 +125,11 @@ pub struct ActiveAccountRequest {
     pub code: String,
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ActivateByLinkRequest {
+    pub token: String,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ResetPasswordRequest {
     pub code: String,
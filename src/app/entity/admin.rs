@@ -0,0 +1,63 @@
+use serde::{Deserialize, Serialize};
+use sqlx::types::chrono::NaiveDateTime;
+use validator::Validate;
+
+use crate::{
+    app::{
+        api::middleware::metrics::RouteSnapshot,
+        entity::health::ReadinessResponse,
+    },
+    models::{
+        account::Account,
+        types::{AccountId, AccountStatus, Language},
+    },
+};
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RequeueResponse {
+    /// Messages moved back from the dead-letter queue.
+    pub requeued: usize,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct BulkAccountsRequest {
+    #[validate(length(min = 1, max = 1000))]
+    pub uids: Vec<AccountId>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SuspendAccountsResponse {
+    /// The accounts found and suspended, the others are skipped.
+    pub suspended: Vec<AccountId>,
+}
+
+/// What operators see at a glance, without a metrics stack.
+#[derive(Debug, Serialize)]
+pub struct StatsResponse {
+    pub routes: Vec<RouteSnapshot>,
+    pub dependencies: ReadinessResponse,
+}
+
+/// An account as listed to admins, without its password hash.
+#[derive(Debug, Serialize)]
+pub struct AccountSummary {
+    pub id: AccountId,
+    pub name: String,
+    pub email: String,
+    pub status: AccountStatus,
+    pub language: Language,
+    pub created_at: NaiveDateTime,
+}
+
+impl From<Account> for AccountSummary {
+    fn from(account: Account) -> Self {
+        Self {
+            id: account.id,
+            name: account.name,
+            email: account.email,
+            status: account.status,
+            language: account.language,
+            created_at: account.created_at,
+        }
+    }
+}
@@ -3,9 +3,14 @@ use axum::{
     Json,
 };
 use hyper::StatusCode;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
+use validator::Validate;
 
-use crate::library::error::AppError;
+use crate::library::error::{
+    error_envelope, success_envelope, ApiInnerError,
+    AppError::{self, ApiError},
+    AppResult,
+};
 
 pub struct AppResponse<'a, T: IntoResponse> {
     pub code: u16,
@@ -38,40 +43,161 @@ impl<'a, T: IntoResponse> From<SuccessResponse<'a, T>> for AppResponse<'a, T> {
 
 impl<'a, U: Serialize> IntoResponse for AppResponse<'a, Json<U>> {
     fn into_response(self) -> Response {
-        let (status, code) = if let Some(app_error) = self.err {
-            AppError::select_status_code(&app_error)
+        let body = if let Some(app_error) = self.err {
+            let (status, code) = AppError::select_status_code(&app_error);
+            (status, Json(error_envelope(code, self.msg)))
         } else {
-            (StatusCode::OK, 0)
+            let data = self.data.map(|d| serde_json::json!(d.0));
+            (StatusCode::OK, Json(success_envelope(self.msg, data)))
         };
-        let body = Json(serde_json::json!({
-            "code": code,
-            "msg": self.msg,
-            "data": self.data.map(|d| d.0)
-        }));
-        (status, body).into_response()
+        body.into_response()
     }
 }
 
 impl<'a, U: Serialize> IntoResponse for SuccessResponse<'a, Json<U>> {
     fn into_response(self) -> Response {
-        let status = StatusCode::OK;
-        let body = Json(serde_json::json!({
-            "code": 0,
-            "msg": self.msg,
-            "data": self.data.map(|d| d.0)
-        }));
-        (status, body).into_response()
+        let data = self.data.map(|d| serde_json::json!(d.0));
+        (StatusCode::OK, Json(success_envelope(self.msg, data))).into_response()
     }
 }
 
 impl<'a> IntoResponse for SuccessResponse<'a, ()> {
     fn into_response(self) -> Response {
-        let status = StatusCode::OK;
-        let body = Json(serde_json::json!({
-            "code": 0,
-            "msg": self.msg,
-            "data": None::<()>
-        }));
-        (status, body).into_response()
+        (StatusCode::OK, Json(success_envelope(self.msg, None))).into_response()
+    }
+}
+
+/// Pagination: `?limit=20&offset=40` skips 40 items and returns 20.
+#[derive(Debug, Clone, Copy, Deserialize, Validate)]
+pub struct PageQuery {
+    #[serde(default = "default_page_limit")]
+    #[validate(range(min = 1, max = 100))]
+    pub limit: i64,
+    #[serde(default)]
+    #[validate(range(min = 0))]
+    pub offset: i64,
+}
+
+const fn default_page_limit() -> i64 {
+    20
+}
+
+/// A page of `items` out of `total`, as requested by a [`PageQuery`].
+#[derive(Debug, Serialize)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    pub total: i64,
+    pub limit: i64,
+    pub offset: i64,
+}
+
+impl<T> Page<T> {
+    pub fn new(items: Vec<T>, total: i64, query: PageQuery) -> Self {
+        Self {
+            items,
+            total,
+            limit: query.limit,
+            offset: query.offset,
+        }
+    }
+}
+
+/// Partial responses: `?fields=email,language` keeps only the listed fields.
+#[derive(Debug, Default, Deserialize)]
+pub struct FieldsQuery {
+    pub fields: Option<String>,
+}
+
+impl FieldsQuery {
+    /// Serializes `value` down to the requested fields, all of which must be
+    /// in `allowed`. Without `fields` the whole value is kept.
+    pub fn select<T: Serialize>(
+        &self,
+        value: &T,
+        allowed: &[&str],
+    ) -> AppResult<serde_json::Value> {
+        let mut value = serde_json::to_value(value).map_err(|e| {
+            anyhow::anyhow!("Error occurred while serializing response: {}", e)
+        })?;
+        let Some(fields) = &self.fields else {
+            return Ok(value);
+        };
+
+        let fields = fields
+            .split(',')
+            .map(str::trim)
+            .filter(|field| !field.is_empty())
+            .collect::<Vec<_>>();
+        if let Some(field) = fields.iter().find(|f| !allowed.contains(f)) {
+            return Err(ApiError(ApiInnerError::UnknownField(
+                (*field).to_string(),
+            )));
+        }
+        if let Some(object) = value.as_object_mut() {
+            object.retain(|key, _| fields.contains(&key.as_str()));
+        }
+        Ok(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    const ALLOWED: &[&str] = &["email", "language", "status"];
+
+    fn user() -> serde_json::Value {
+        json!({
+            "email": "vainjoker@tuta.io",
+            "language": "en-US",
+            "status": "active",
+        })
+    }
+
+    fn query(fields: Option<&str>) -> FieldsQuery {
+        FieldsQuery {
+            fields: fields.map(ToString::to_string),
+        }
+    }
+
+    #[test]
+    fn test_select_fields_subset() {
+        let selected = query(Some("email, language"))
+            .select(&user(), ALLOWED)
+            .unwrap();
+        assert_eq!(
+            selected,
+            json!({"email": "vainjoker@tuta.io", "language": "en-US"})
+        );
+
+        let all = query(None).select(&user(), ALLOWED).unwrap();
+        assert_eq!(all, user());
+    }
+
+    #[test]
+    fn test_select_fields_rejects_unknown() {
+        let result = query(Some("email,password")).select(&user(), ALLOWED);
+        assert!(matches!(
+            result,
+            Err(ApiError(ApiInnerError::UnknownField(field))) if field == "password"
+        ));
+    }
+
+    #[test]
+    fn test_page_query_bounds() {
+        let page = |query: &str| {
+            let uri = format!("/?{query}").parse().unwrap();
+            axum::extract::Query::<PageQuery>::try_from_uri(&uri)
+                .unwrap()
+                .0
+        };
+        let default = page("");
+        assert_eq!((default.limit, default.offset), (20, 0));
+        assert!(page("limit=100&offset=40").validate().is_ok());
+        for query in ["limit=0", "limit=101", "offset=-1"] {
+            assert!(page(query).validate().is_err(), "{query}");
+        }
     }
 }
@@ -0,0 +1,92 @@
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::models::{
+    account::Account,
+    types::{AccountId, Language},
+};
+
+/// A domain event published to `MQ_EVENT_EXCHANGE` under `ROUTING_KEY`.
+pub trait Event: Serialize {
+    const ROUTING_KEY: &'static str;
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UserRegistered {
+    pub uid: AccountId,
+    pub email: String,
+    /// As its BCP 47 tag, e.g. `en-US`, for consumers outside the app.
+    #[serde(
+        serialize_with = "serialize_tag",
+        deserialize_with = "deserialize_tag"
+    )]
+    pub language: Language,
+}
+
+fn serialize_tag<S: Serializer>(
+    language: &Language,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(language.tag())
+}
+
+fn deserialize_tag<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Language, D::Error> {
+    let tag = String::deserialize(deserializer)?;
+    Language::from_tag(&tag).ok_or_else(|| {
+        serde::de::Error::custom(format!("unknown language tag `{tag}`"))
+    })
+}
+
+impl Event for UserRegistered {
+    const ROUTING_KEY: &'static str = "user.registered";
+}
+
+impl From<&Account> for UserRegistered {
+    fn from(user: &Account) -> Self {
+        Self {
+            uid: user.id,
+            email: user.email.clone(),
+            language: user.language,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use assert_json_diff::assert_json_eq;
+    use chrono::NaiveDateTime;
+
+    use super::*;
+    use crate::models::types::AccountStatus;
+
+    #[test]
+    fn test_user_registered_payload() {
+        let user = Account {
+            id: AccountId(6192889942050345985),
+            name: "VJ".to_string(),
+            email: "vainjoker@tuta.io".to_string(),
+            password: String::new(),
+            status: AccountStatus::Inactive,
+            language: Language::EnUs,
+            created_at: NaiveDateTime::default(),
+            updated_at: None,
+            deleted_at: None,
+            last_login_at: None,
+            password_changed_at: None,
+        };
+
+        let payload =
+            serde_json::to_value(UserRegistered::from(&user)).unwrap();
+        assert_json_eq!(
+            payload,
+            serde_json::json!({
+                "uid": 6192889942050345985_i64,
+                "email": "vainjoker@tuta.io",
+                "language": "en-US",
+            })
+        );
+        let event = serde_json::from_value::<UserRegistered>(payload).unwrap();
+        assert_eq!(event.language, Language::EnUs);
+    }
+}
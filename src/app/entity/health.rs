@@ -0,0 +1,41 @@
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DependencyStatus {
+    Up,
+    Down,
+}
+
+impl From<bool> for DependencyStatus {
+    fn from(healthy: bool) -> Self {
+        if healthy {
+            Self::Up
+        } else {
+            Self::Down
+        }
+    }
+}
+
+/// Status of each dependency, `status` is only up when all of them are.
+#[derive(Debug, PartialEq, Eq, Serialize)]
+pub struct ReadinessResponse {
+    pub status: DependencyStatus,
+    pub checks: BTreeMap<&'static str, DependencyStatus>,
+}
+
+impl ReadinessResponse {
+    pub fn new(checks: &[(&'static str, bool)]) -> Self {
+        let checks = checks
+            .iter()
+            .map(|&(name, healthy)| (name, healthy.into()))
+            .collect::<BTreeMap<_, DependencyStatus>>();
+        let status = checks
+            .values()
+            .all(|status| *status == DependencyStatus::Up)
+            .into();
+        Self { status, checks }
+    }
+}
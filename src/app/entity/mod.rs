@@ -1,2 +1,5 @@
 pub mod account;
+pub mod admin;
 pub mod common;
+pub mod event;
+pub mod health;
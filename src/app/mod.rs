@@ -5,14 +5,15 @@ pub mod service;
 
 use std::sync::Arc;
 
-use crate::app::bootstrap::AppState;
+use crate::app::bootstrap::{db_monitor, reload_signal, AppState};
 
 pub async fn serve() {
     let app_state = Arc::new(AppState::init().await);
 
-    AppState::serve(app_state.clone()).await;
+    tokio::spawn(reload_signal());
+    tokio::spawn(db_monitor(app_state.clone()));
 
-    api::Server::init(app_state.clone()).serve().await;
+    AppState::serve(app_state.clone()).await;
 
-    app_state.services.shutdown().await;
+    api::Server::init(app_state).serve().await;
 }
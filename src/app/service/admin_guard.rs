@@ -0,0 +1,200 @@
+use std::time::Duration;
+
+use crate::{
+    app::bootstrap::{
+        constants::{self, MQ_SEND_EMAIL_DLX, MQ_SEND_EMAIL_QUEUE},
+        AppState,
+    },
+    library::{
+        cfg::{self, AdminAlertConfig},
+        error::{ApiInnerError, AppError::ApiError, AppResult},
+        mailor::Email,
+    },
+    models::types::AccountId,
+};
+
+/// Admin actions that can do a lot of damage in bulk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SensitiveAction {
+    Suspension,
+    Export,
+}
+
+impl SensitiveAction {
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Suspension => "suspension",
+            Self::Export => "export",
+        }
+    }
+}
+
+/// Raised when an admin performs more sensitive actions in a window than
+/// `app.admin_alert.threshold` allows.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AdminAlert {
+    pub uid: AccountId,
+    pub action: SensitiveAction,
+    pub count: i64,
+    pub window: u64,
+}
+
+impl AdminAlert {
+    /// Returns the alert when `count` is the first action past the
+    /// threshold, so that each burst is reported only once per window.
+    pub const fn detect(
+        uid: AccountId,
+        action: SensitiveAction,
+        count: i64,
+        cfg: &AdminAlertConfig,
+    ) -> Option<Self> {
+        if count != cfg.threshold + 1 {
+            return None;
+        }
+        Some(Self {
+            uid,
+            action,
+            count,
+            window: cfg.window,
+        })
+    }
+
+    fn message(&self) -> String {
+        format!(
+            "Admin {} performed more than {} sensitive actions within {}s, \
+             the last one being a {}",
+            self.uid,
+            self.count - 1,
+            self.window,
+            self.action.as_str()
+        )
+    }
+}
+
+/// Counts a sensitive action of admin `uid` and raises an alert once the
+/// threshold is exceeded. With `lockout` set, actions beyond the threshold
+/// are rejected until the window expires.
+pub async fn record(
+    state: &AppState,
+    uid: AccountId,
+    action: SensitiveAction,
+) -> AppResult<()> {
+    let cfg = cfg::config().app.admin_alert.clone();
+    let key = format!("{}:{}", constants::REDIS_ADMIN_ACTIONS_KEY, uid);
+    let mut redis = state.get_redis().await?;
+    let count = redis
+        .incr_ex(&key, 1, Duration::from_secs(cfg.window))
+        .await?;
+
+    if let Some(alert) = AdminAlert::detect(uid, action, count, &cfg) {
+        raise(state, &alert, &cfg).await;
+    }
+    if cfg.lockout && count > cfg.threshold {
+        let ttl = redis.ttl(&key).await?;
+        return check_lockout(count, ttl, &cfg);
+    }
+    Ok(())
+}
+
+/// Rejects the `count`th action once past the threshold, until the window
+/// expires in `ttl`.
+fn check_lockout(
+    count: i64,
+    ttl: Option<Duration>,
+    cfg: &AdminAlertConfig,
+) -> AppResult<()> {
+    if cfg.lockout && count > cfg.threshold {
+        let retry_after = ttl.unwrap_or(Duration::from_secs(cfg.window));
+        return Err(ApiError(ApiInnerError::too_many_requests(retry_after)));
+    }
+    Ok(())
+}
+
+async fn raise(state: &AppState, alert: &AdminAlert, cfg: &AdminAlertConfig) {
+    let message = alert.message();
+    tracing::warn!("🚨 {message}");
+
+    if let Some(to) = &cfg.email {
+        if let Err(e) = send_alert_email(state, to, &message).await {
+            tracing::error!("Failed to send admin alert email: {e}");
+        }
+    }
+}
+
+async fn send_alert_email(
+    state: &AppState,
+    to: &str,
+    message: &str,
+) -> AppResult<()> {
+    let email = Email::new(to, "Admin action anomaly", message);
+    let email_json = serde_json::to_string(&email).map_err(|e| {
+        anyhow::anyhow!("Error occurred while sending email: {}", e)
+    })?;
+    state
+        .get_mq()?
+        .basic_send_with_dlq(
+            MQ_SEND_EMAIL_QUEUE,
+            MQ_SEND_EMAIL_DLX,
+            &email_json,
+        )
+        .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect_alerts_once_past_threshold() {
+        let cfg = AdminAlertConfig {
+            threshold: 3,
+            window: 60,
+            lockout: false,
+            email: None,
+        };
+        let detect = |count| {
+            AdminAlert::detect(
+                AccountId(1),
+                SensitiveAction::Export,
+                count,
+                &cfg,
+            )
+        };
+
+        for count in 1..=3 {
+            assert_eq!(detect(count), None);
+        }
+        assert_eq!(
+            detect(4),
+            Some(AdminAlert {
+                uid: AccountId(1),
+                action: SensitiveAction::Export,
+                count: 4,
+                window: 60,
+            })
+        );
+        assert_eq!(detect(5), None);
+    }
+
+    #[test]
+    fn test_lockout_hints_window_expiry() {
+        let cfg = AdminAlertConfig {
+            threshold: 3,
+            window: 60,
+            lockout: true,
+            email: None,
+        };
+        assert!(check_lockout(3, Some(Duration::from_secs(20)), &cfg).is_ok());
+        for (ttl, retry_after) in
+            [(Some(Duration::from_secs(20)), 20), (None, 60)]
+        {
+            assert!(matches!(
+                check_lockout(4, ttl, &cfg),
+                Err(ApiError(ApiInnerError::TooManyRequests {
+                    retry_after_secs
+                })) if retry_after_secs == retry_after
+            ));
+        }
+    }
+}
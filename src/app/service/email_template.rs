@@ -0,0 +1,159 @@
+use crate::models::types::Language;
+
+/// Emails carrying a one-time code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CodeEmail {
+    Activation,
+    ResetPassword,
+}
+
+impl CodeEmail {
+    /// Subject and body with `code` in the account's language.
+    pub fn content(
+        self,
+        language: Language,
+        code: &str,
+    ) -> (&'static str, String) {
+        let (subject, label) = match (self, language) {
+            (Self::Activation, Language::EnUs) => {
+                ("Active your account", "Active Code")
+            }
+            (Self::Activation, Language::ZhCn) => ("激活您的账户", "激活码"),
+            (Self::Activation, Language::FrFr) => {
+                ("Activez votre compte", "Code d'activation")
+            }
+            (Self::Activation, Language::EsEs) => {
+                ("Activa tu cuenta", "Código de activación")
+            }
+            (Self::ResetPassword, Language::EnUs) => {
+                ("Reset Password", "ResetPassword Code")
+            }
+            (Self::ResetPassword, Language::ZhCn) => {
+                ("重置密码", "重置密码验证码")
+            }
+            (Self::ResetPassword, Language::FrFr) => {
+                ("Réinitialiser le mot de passe", "Code de réinitialisation")
+            }
+            (Self::ResetPassword, Language::EsEs) => {
+                ("Restablecer contraseña", "Código de restablecimiento")
+            }
+        };
+        (subject, format!("{label}: {code}"))
+    }
+}
+
+/// Activation `body` followed by `link`, for accounts that would rather click
+/// than type the code.
+pub fn with_activation_link(
+    language: Language,
+    body: &str,
+    link: &str,
+) -> String {
+    let label = match language {
+        Language::EnUs => "Or activate it with this link",
+        Language::ZhCn => "或通过此链接激活",
+        Language::FrFr => "Ou activez-le avec ce lien",
+        Language::EsEs => "O actívala con este enlace",
+    };
+    format!("{body}\n{label}: {link}")
+}
+
+/// Subject and body of the welcome email in the account's language.
+pub const fn welcome_content(
+    language: Language,
+) -> (&'static str, &'static str) {
+    match language {
+        Language::EnUs => (
+            "Welcome to iwi",
+            "Thanks for signing up, we're glad to have you!",
+        ),
+        Language::ZhCn => ("欢迎使用 iwi", "感谢您的注册，欢迎加入！"),
+        Language::FrFr => (
+            "Bienvenue sur iwi",
+            "Merci de votre inscription, nous sommes ravis de vous compter parmi nous !",
+        ),
+        Language::EsEs => (
+            "Bienvenido a iwi",
+            "¡Gracias por registrarte, nos alegra tenerte con nosotros!",
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::library::mailor::Email;
+
+    const LANGUAGES: [Language; 4] = [
+        Language::EnUs,
+        Language::ZhCn,
+        Language::FrFr,
+        Language::EsEs,
+    ];
+
+    fn assert_distinct(subjects: &[&str]) {
+        for (i, subject) in subjects.iter().enumerate() {
+            assert!(!subjects[i + 1..].contains(subject));
+        }
+    }
+
+    #[test]
+    fn test_code_email_is_localized() {
+        for email in [CodeEmail::Activation, CodeEmail::ResetPassword] {
+            let contents =
+                LANGUAGES.map(|language| email.content(language, "123456"));
+            assert_distinct(&contents.each_ref().map(|c| c.0));
+            assert!(contents.iter().all(|c| c.1.ends_with(": 123456")));
+        }
+    }
+
+    #[test]
+    fn test_activation_link_is_localized() {
+        let bodies = LANGUAGES.map(|language| {
+            with_activation_link(language, "body", "https://iwi/activate")
+        });
+        assert_distinct(&bodies.each_ref().map(String::as_str));
+        assert!(bodies.iter().all(|b| b.starts_with("body\n")
+            && b.ends_with(": https://iwi/activate")));
+    }
+
+    #[test]
+    fn test_welcome_content_is_localized() {
+        assert_distinct(&LANGUAGES.map(|language| welcome_content(language).0));
+    }
+
+    #[test]
+    fn test_templates_survive_the_queue() {
+        for language in LANGUAGES {
+            let (subject, code_body) =
+                CodeEmail::Activation.content(language, "123456");
+            let link_body = with_activation_link(
+                language,
+                &code_body,
+                "https://iwi/activate?token=a.b.c",
+            );
+            let (welcome_subject, welcome_body) = welcome_content(language);
+            for (subject, body) in [
+                (subject, code_body.as_str()),
+                (subject, &link_body),
+                (welcome_subject, welcome_body),
+            ] {
+                let html =
+                    format!("<p style=\"white-space: pre-line\">{body}</p>");
+                let json = serde_json::json!({
+                    "to": "vainjoker@tuta.io",
+                    "subject": subject,
+                    "body": body,
+                    "html": html,
+                    "config": { "username": "iwi@tuta.io", "password": "", "host": "" },
+                })
+                .to_string();
+
+                let queued = serde_json::from_str::<Email>(&json).unwrap();
+                assert_eq!(queued.subject, subject);
+                assert_eq!(queued.body, body);
+                assert_eq!(queued.html.as_deref(), Some(html.as_str()));
+            }
+        }
+    }
+}
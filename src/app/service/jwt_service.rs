@@ -1,4 +1,8 @@
-use std::sync::{Arc, OnceLock};
+use std::{
+    fs,
+    sync::{Arc, OnceLock},
+    time::Duration,
+};
 
 use axum::{
     async_trait, extract::FromRequestParts, http::request::Parts,
@@ -9,33 +13,55 @@ use axum_extra::{
     TypedHeader,
 };
 use jsonwebtoken::{
-    decode, encode, DecodingKey, EncodingKey, Header, Validation,
+    decode, encode, errors::ErrorKind, Algorithm, DecodingKey, EncodingKey,
+    Header, Validation,
 };
 use serde::{Deserialize, Serialize};
 
 use crate::{
-    app::bootstrap::AppState,
+    app::bootstrap::{constants, AppState},
     library::{
-        cfg,
+        cfg::{self, JWTConfig, JwtAlgorithm},
         error::{AppError, AppError::AuthError, AppResult, AuthInnerError},
+        Redis,
+    },
+    models::{
+        account::Account,
+        types::{AccountId, AccountStatus, Language},
     },
-    models::{account::Account, types::AccountStatus},
 };
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Claims {
-    pub uid: i64,
+    pub uid: AccountId,
     pub email: String,
     pub status: AccountStatus,
+    /// Whether the session was opened with "remember me", carried over on
+    /// refresh so the longer expiry keeps applying.
+    #[serde(default)]
+    pub remember_me: bool,
+    /// Unique id of the token, what [`Claims::revoke`] blacklists.
+    #[serde(default)]
+    pub jti: String,
+    /// The user's token epoch at issue time, tokens of an older epoch were
+    /// invalidated by [`Claims::invalidate_sessions`].
+    #[serde(default)]
+    pub epoch: i64,
+    /// Unknown in tokens issued before it was added.
+    #[serde(default)]
+    pub language: Option<Language>,
     pub iat: usize,
     pub exp: usize,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct UserInfo {
-    pub uid: i64,
+    pub uid: AccountId,
     pub email: String,
     pub status: AccountStatus,
+    pub remember_me: bool,
+    pub epoch: i64,
+    pub language: Language,
 }
 
 #[derive(Debug, Serialize)]
@@ -47,49 +73,114 @@ pub struct TokenSchema {
 #[derive(Debug, Serialize, Deserialize)]
 pub struct RefreshTokenRequest {
     pub refresh_token: String,
+    /// The `device_id` the session was opened with, if any.
+    #[serde(default)]
+    pub device_id: Option<String>,
+}
+
+/// What signs and verifies a token type, per `alg` of its [`JWTConfig`].
+struct TokenKeys {
+    algorithm: Algorithm,
+    encoding: EncodingKey,
+    decoding: DecodingKey,
+}
+
+impl TokenKeys {
+    fn from_config(cfg: &JWTConfig) -> anyhow::Result<Self> {
+        match cfg.alg {
+            JwtAlgorithm::HS256 => Ok(Self::hmac(cfg.secret.as_bytes())),
+            JwtAlgorithm::RS256 => {
+                let read = |file: &Option<String>, name| {
+                    let file = file.as_deref().ok_or_else(|| {
+                        anyhow::anyhow!("RS256 requires `{name}`")
+                    })?;
+                    fs::read(file).map_err(|e| {
+                        anyhow::anyhow!("Failed to read {name}: {e} - {file}")
+                    })
+                };
+                Self::rsa(
+                    &read(&cfg.private_key_file, "private_key_file")?,
+                    &read(&cfg.public_key_file, "public_key_file")?,
+                )
+            }
+        }
+    }
+
+    fn hmac(secret: &[u8]) -> Self {
+        Self {
+            algorithm: Algorithm::HS256,
+            encoding: EncodingKey::from_secret(secret),
+            decoding: DecodingKey::from_secret(secret),
+        }
+    }
+
+    fn rsa(private_pem: &[u8], public_pem: &[u8]) -> anyhow::Result<Self> {
+        Ok(Self {
+            algorithm: Algorithm::RS256,
+            encoding: EncodingKey::from_rsa_pem(private_pem)?,
+            decoding: DecodingKey::from_rsa_pem(public_pem)?,
+        })
+    }
 }
 
-pub struct TokenSecretInfo<'a> {
-    secret: &'a [u8],
+pub struct TokenSecretInfo {
+    keys: TokenKeys,
     expiration: i64,
+    remember_me_expiration: i64,
+    leeway: u64,
 }
 
-impl<'a> TokenSecretInfo<'a> {
+impl TokenSecretInfo {
     fn new(token_type: TokenType) -> Self {
+        let keys = TokenKeys::from_config(&Self::get_config(token_type))
+            .unwrap_or_else(|e| {
+                panic!("💥 Failed to load the {token_type:?} token keys: {e}")
+            });
         Self {
-            secret: Self::get_secret(token_type),
+            keys,
             expiration: Self::get_secret_expiration(token_type),
+            remember_me_expiration: Self::get_remember_me_expiration(
+                token_type,
+            ),
+            leeway: Self::get_config(token_type).leeway,
         }
     }
 
-    fn get_secret(token_type: TokenType) -> &'a [u8] {
+    fn get_config(token_type: TokenType) -> JWTConfig {
+        let cfg = cfg::config();
         match token_type {
-            TokenType::ACCESS => cfg::config().app.access_token.secret.as_ref(),
-            TokenType::REFRESH => {
-                cfg::config().app.refresh_token.secret.as_ref()
+            TokenType::ACCESS => cfg.app.access_token.clone(),
+            TokenType::REFRESH => cfg.app.refresh_token.clone(),
+            // Only read once configured, see `Claims::activation_info`.
+            TokenType::ACTIVATION => {
+                cfg.app.activation_token.clone().unwrap_or_default()
             }
         }
     }
 
     fn get_secret_expiration(token_type: TokenType) -> i64 {
-        match token_type {
-            TokenType::ACCESS => {
-                cfg::config().app.access_token.secret_expiration.into()
-            }
-            TokenType::REFRESH => {
-                cfg::config().app.refresh_token.secret_expiration.into()
-            }
-        }
+        Self::get_config(token_type).secret_expiration.into()
+    }
+
+    fn get_remember_me_expiration(token_type: TokenType) -> i64 {
+        let token = Self::get_config(token_type);
+        token
+            .remember_me_expiration
+            .unwrap_or(token.secret_expiration)
+            .into()
     }
 }
 
-static ACCESS_INFO: OnceLock<Arc<TokenSecretInfo<'static>>> = OnceLock::new();
-static REFRESH_INFO: OnceLock<Arc<TokenSecretInfo<'static>>> = OnceLock::new();
+static ACCESS_INFO: OnceLock<Arc<TokenSecretInfo>> = OnceLock::new();
+static REFRESH_INFO: OnceLock<Arc<TokenSecretInfo>> = OnceLock::new();
+static ACTIVATION_INFO: OnceLock<Arc<TokenSecretInfo>> = OnceLock::new();
 
 #[derive(Debug, Clone, Copy, Serialize, Deserialize)]
 pub enum TokenType {
     ACCESS,
     REFRESH,
+    /// Single-use tokens in emailed activation links.
+    ACTIVATION,
 }
 
 pub trait TokenAuth {
@@ -97,36 +188,60 @@ pub trait TokenAuth {
     fn parse_token(&self, token: &str) -> AppResult<Claims>;
 }
 
-impl TokenAuth for TokenSecretInfo<'_> {
-    fn generate_token(&self, credential: &UserInfo) -> AppResult<String> {
+impl TokenSecretInfo {
+    fn claims(&self, credential: &UserInfo) -> Claims {
         let now = chrono::Utc::now();
-        let duration = self.expiration;
-        let claims = Claims {
+        let duration = if credential.remember_me {
+            self.remember_me_expiration
+        } else {
+            self.expiration
+        };
+        Claims {
             uid: credential.uid,
             email: credential.email.clone(),
             status: credential.status,
+            remember_me: credential.remember_me,
+            jti: uuid::Uuid::new_v4().to_string(),
+            epoch: credential.epoch,
+            language: Some(credential.language),
             exp: (now + chrono::Duration::seconds(duration)).timestamp()
                 as usize,
             iat: now.timestamp() as usize,
-        };
+        }
+    }
 
-        let token = encode(
-            &Header::default(),
-            &claims,
-            &EncodingKey::from_secret(self.secret),
+    fn encode(&self, claims: &Claims) -> AppResult<String> {
+        encode(
+            &Header::new(self.keys.algorithm),
+            claims,
+            &self.keys.encoding,
         )
-        .map_err(|_| AuthError(AuthInnerError::TokenCreation))?;
+        .map_err(|e| {
+            // Only logged, the client just learns the signing failed.
+            tracing::error!("Failed to sign a token: {e}");
+            AuthError(AuthInnerError::TokenCreation)
+        })
+    }
+}
 
-        Ok(token)
+impl TokenAuth for TokenSecretInfo {
+    fn generate_token(&self, credential: &UserInfo) -> AppResult<String> {
+        self.encode(&self.claims(credential))
     }
 
     fn parse_token(&self, token: &str) -> AppResult<Claims> {
-        let token_data = decode::<Claims>(
-            token,
-            &DecodingKey::from_secret(self.secret),
-            &Validation::default(),
-        )
-        .map_err(|_| AuthError(AuthInnerError::InvalidToken))?;
+        let mut validation = Validation::new(self.keys.algorithm);
+        validation.leeway = self.leeway;
+        let token_data =
+            decode::<Claims>(token, &self.keys.decoding, &validation).map_err(
+                |e| match e.kind() {
+                    // The client should refresh rather than log in again.
+                    ErrorKind::ExpiredSignature => {
+                        AuthError(AuthInnerError::TokenExpired)
+                    }
+                    _ => AuthError(AuthInnerError::InvalidToken),
+                },
+            )?;
 
         Ok(token_data.claims)
     }
@@ -155,19 +270,26 @@ where
 }
 
 impl Claims {
-    pub fn generate_tokens(credential: &UserInfo) -> AppResult<TokenSchema> {
+    /// Returns the claims of the refresh token too, for rotating it.
+    fn generate_tokens(
+        credential: &UserInfo,
+    ) -> AppResult<(TokenSchema, Self)> {
         let access_info = ACCESS_INFO
             .get_or_init(|| Arc::new(TokenSecretInfo::new(TokenType::ACCESS)));
         let refresh_info = REFRESH_INFO
             .get_or_init(|| Arc::new(TokenSecretInfo::new(TokenType::REFRESH)));
 
         let access_token = access_info.generate_token(credential)?;
-        let refresh_token = refresh_info.generate_token(credential)?;
+        let refresh = refresh_info.claims(credential);
+        let refresh_token = refresh_info.encode(&refresh)?;
 
-        Ok(TokenSchema {
-            refresh_token,
-            access_token,
-        })
+        Ok((
+            TokenSchema {
+                refresh_token,
+                access_token,
+            },
+            refresh,
+        ))
     }
 
     pub fn parse_token(
@@ -180,6 +302,8 @@ impl Claims {
                 .get_or_init(|| Arc::new(TokenSecretInfo::new(token_type))),
             TokenType::REFRESH => REFRESH_INFO
                 .get_or_init(|| Arc::new(TokenSecretInfo::new(token_type))),
+            TokenType::ACTIVATION => Self::activation_info()
+                .ok_or(AuthError(AuthInnerError::InvalidToken))?,
         };
         let claims = info.parse_token(token)?;
         if (verified && claims.status == AccountStatus::Active)
@@ -190,29 +314,701 @@ impl Claims {
         Err(AuthError(AuthInnerError::InvalidToken))
     }
 
+    /// Opens a session of `user` on `device`, the client's stable id of it.
     pub async fn generate_tokens_for_user(
         user: &Account,
+        remember_me: bool,
+        device: Option<&str>,
+        redis: &mut Redis,
     ) -> AppResult<TokenSchema> {
         let user_info = UserInfo {
             uid: user.id,
             email: user.email.clone(),
             status: user.status,
+            remember_me,
+            epoch: Self::token_epoch(user.id, redis).await?,
+            language: user.language,
         };
-        let token = Claims::generate_tokens(&user_info)?;
+        let (tokens, refresh) = Claims::generate_tokens(&user_info)?;
+        refresh.activate(device, redis).await?;
 
-        Ok(token)
+        Ok(tokens)
     }
 
+    /// Trades a refresh token for a new pair. Each refresh token can only be
+    /// used once: presenting one that was already rotated out means it
+    /// leaked, so every token of the user is invalidated. So does presenting
+    /// one from another `device` with `app.bind_sessions_to_device` set.
     pub async fn refresh_token(
         token: &str,
+        device: Option<&str>,
         state: Arc<AppState>,
     ) -> AppResult<TokenSchema> {
         let claims = Claims::parse_token(token, TokenType::REFRESH, false)?;
+        let mut redis = state.get_redis().await?;
+        claims.ensure_not_revoked(&mut redis).await?;
 
-        let user = Account::fetch_user_by_uid(state.get_db(), claims.uid)
+        let user = Account::fetch_user_by_uid(state.get_read_db(), claims.uid)
             .await?
             .ok_or(AuthError(AuthInnerError::WrongCredentials))?;
+        // The refresh token may predate the suspension.
+        Self::ensure_not_suspended(&user)?;
+        claims.ensure_issued_after_password_change(&user)?;
+
+        let user_info = UserInfo {
+            uid: user.id,
+            email: user.email.clone(),
+            status: user.status,
+            remember_me: claims.remember_me,
+            epoch: claims.epoch,
+            language: user.language,
+        };
+        let (tokens, refresh) = Claims::generate_tokens(&user_info)?;
+        let bind_device = cfg::config().app.bind_sessions_to_device;
+        claims
+            .rotate(&refresh, device, bind_device, &mut redis)
+            .await?;
+        Ok(tokens)
+    }
+
+    /// Marks the refresh token as the active one of its chain, storing the
+    /// `device` it's used from. Each device has a chain of its own.
+    async fn activate(
+        &self,
+        device: Option<&str>,
+        redis: &mut Redis,
+    ) -> AppResult<()> {
+        redis
+            .set_ex(&self.refresh_key(), device.unwrap_or_default(), self.ttl())
+            .await?;
+        Ok(())
+    }
+
+    /// Replaces `self` with `next` as the active refresh token, revoking
+    /// every token of the user when `self` isn't active anymore, or with
+    /// `bind_device` when it's used from another `device` than it was
+    /// issued to. A session opened without a device, like on activation,
+    /// gets bound to the one of its first refresh.
+    async fn rotate(
+        &self,
+        next: &Self,
+        device: Option<&str>,
+        bind_device: bool,
+        redis: &mut Redis,
+    ) -> AppResult<()> {
+        if bind_device {
+            let bound = redis.get::<String>(&self.refresh_key()).await?;
+            if bound.is_some_and(|bound| {
+                !bound.is_empty() && bound != device.unwrap_or_default()
+            }) {
+                tracing::warn!(
+                    "Refresh token of user {} used from another device, \
+                     revoking all tokens",
+                    self.uid
+                );
+                Self::invalidate_sessions(self.uid, redis).await?;
+                return Err(AuthError(AuthInnerError::InvalidToken));
+            }
+        }
+
+        // Only one of concurrent rotations gets to delete the key.
+        if redis.del(&self.refresh_key()).await? {
+            return next.activate(device, redis).await;
+        }
+
+        tracing::warn!(
+            "Reuse of a rotated refresh token of user {}, revoking all tokens",
+            self.uid
+        );
+        Self::invalidate_sessions(self.uid, redis).await?;
+        Err(AuthError(AuthInnerError::InvalidToken))
+    }
+
+    fn refresh_key(&self) -> String {
+        format!(
+            "{}:{}:{}",
+            constants::REDIS_REFRESH_TOKEN_KEY,
+            self.uid,
+            self.jti
+        )
+    }
+
+    /// Time until the token expires, at least a second.
+    fn ttl(&self) -> Duration {
+        let now = chrono::Utc::now().timestamp().unsigned_abs();
+        Duration::from_secs((self.exp as u64).saturating_sub(now).max(1))
+    }
+
+    /// `None` unless `app.activation_token` is set, which enables
+    /// activation links.
+    fn activation_info() -> Option<&'static Arc<TokenSecretInfo>> {
+        cfg::config().app.activation_token.as_ref()?;
+        Some(ACTIVATION_INFO.get_or_init(|| {
+            Arc::new(TokenSecretInfo::new(TokenType::ACTIVATION))
+        }))
+    }
+
+    /// Mints the token of an activation link for `user`, `None` when
+    /// activation links are disabled.
+    pub fn generate_activation_token(
+        user: &Account,
+    ) -> AppResult<Option<String>> {
+        let Some(info) = Self::activation_info() else {
+            return Ok(None);
+        };
+        let user_info = UserInfo {
+            uid: user.id,
+            email: user.email.clone(),
+            status: user.status,
+            remember_me: false,
+            epoch: 0,
+            language: user.language,
+        };
+        info.generate_token(&user_info).map(Some)
+    }
+
+    /// Parses the token of an activation link and uses it up, failing with
+    /// `InvalidToken` when it was used before.
+    pub async fn redeem_activation_token(
+        token: &str,
+        redis: &mut Redis,
+    ) -> AppResult<Self> {
+        let claims = Self::parse_token(token, TokenType::ACTIVATION, false)?;
+        let leeway = Self::activation_info().map_or(0, |info| info.leeway);
+        claims.redeem(leeway, redis).await?;
+        Ok(claims)
+    }
+
+    /// Marks the single-use token as used until it expires, `leeway`
+    /// included, failing with `InvalidToken` when it already was.
+    async fn redeem(&self, leeway: u64, redis: &mut Redis) -> AppResult<()> {
+        let key =
+            format!("{}:{}", constants::REDIS_REVOKED_TOKEN_KEY, self.jti);
+        let ttl = self.ttl() + Duration::from_secs(leeway);
+        if !redis.set_nx_ex(&key, 1, ttl).await? {
+            return Err(AuthError(AuthInnerError::InvalidToken));
+        }
+        Ok(())
+    }
+
+    /// Blacklists the token `jti` until it expires at `exp`, leeway
+    /// included.
+    pub async fn revoke(
+        jti: &str,
+        exp: usize,
+        redis: &mut Redis,
+    ) -> AppResult<()> {
+        let cfg = cfg::config();
+        let leeway = cfg
+            .app
+            .access_token
+            .leeway
+            .max(cfg.app.refresh_token.leeway);
+        let expires_at = exp as u64 + leeway;
+        let now = chrono::Utc::now().timestamp().unsigned_abs();
+        if expires_at <= now {
+            return Ok(());
+        }
+        let key = format!("{}:{}", constants::REDIS_REVOKED_TOKEN_KEY, jti);
+        redis
+            .set_ex(&key, 1, Duration::from_secs(expires_at - now))
+            .await?;
+        Ok(())
+    }
+
+    /// Invalidates every token issued to `uid` so far, by moving on to the
+    /// next token epoch.
+    pub async fn invalidate_sessions(
+        uid: AccountId,
+        redis: &mut Redis,
+    ) -> AppResult<()> {
+        Self::invalidate_sessions_of(&[uid], redis).await
+    }
+
+    /// [`Claims::invalidate_sessions`] for a batch of users, in a single
+    /// round trip.
+    pub async fn invalidate_sessions_of(
+        uids: &[AccountId],
+        redis: &mut Redis,
+    ) -> AppResult<()> {
+        if uids.is_empty() {
+            return Ok(());
+        }
+        redis
+            .pipeline::<(), _>(|pipe, prefix| {
+                for uid in uids {
+                    let key =
+                        format!("{}:{}", constants::REDIS_TOKEN_EPOCH_KEY, uid);
+                    pipe.incr(prefix.key(&key), 1).ignore();
+                }
+            })
+            .await?;
+        Ok(())
+    }
+
+    async fn token_epoch(uid: AccountId, redis: &mut Redis) -> AppResult<i64> {
+        let key = format!("{}:{}", constants::REDIS_TOKEN_EPOCH_KEY, uid);
+        Ok(redis.get::<i64>(&key).await?.unwrap_or_default())
+    }
+
+    /// Fails with `InvalidToken` once the token was [revoked](Claims::revoke)
+    /// or its epoch [invalidated](Claims::invalidate_sessions).
+    pub async fn ensure_not_revoked(&self, redis: &mut Redis) -> AppResult<()> {
+        let revoked =
+            format!("{}:{}", constants::REDIS_REVOKED_TOKEN_KEY, self.jti);
+        let epoch =
+            format!("{}:{}", constants::REDIS_TOKEN_EPOCH_KEY, self.uid);
+        let values = redis.mget::<i64>(&[&revoked, &epoch]).await?;
+        if values[0].is_some() || values[1].unwrap_or_default() > self.epoch {
+            return Err(AuthError(AuthInnerError::InvalidToken));
+        }
+        Ok(())
+    }
+
+    /// Fails with `InvalidToken` when the token was issued before the last
+    /// password change of `user`.
+    fn ensure_issued_after_password_change(
+        &self,
+        user: &Account,
+    ) -> AppResult<()> {
+        let Some(changed_at) = user.password_changed_at else {
+            return Ok(());
+        };
+        // `iat` has whole seconds, a token issued in the second of the change
+        // is let through.
+        if (self.iat as i64) < changed_at.and_utc().timestamp() {
+            return Err(AuthError(AuthInnerError::InvalidToken));
+        }
+        Ok(())
+    }
+
+    fn ensure_not_suspended(user: &Account) -> AppResult<()> {
+        if user.status == AccountStatus::Suspend {
+            return Err(AuthError(AuthInnerError::AccountSuspended));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+impl Claims {
+    /// Signs an access token with the configured keys, or with test keys
+    /// when there's no configuration, for tests of the routes behind auth.
+    pub fn test_access_token(credential: &UserInfo) -> String {
+        ACCESS_INFO
+            .get_or_init(|| {
+                Arc::new(TokenSecretInfo {
+                    keys: TokenKeys::hmac(b"test_access_token_secret"),
+                    expiration: 3600,
+                    remember_me_expiration: 86400,
+                    leeway: 60,
+                })
+            })
+            .generate_token(credential)
+            .unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::StatusCode;
+    use sqlx::types::chrono::NaiveDateTime;
+
+    use super::*;
+    use crate::library::Redisor;
+
+    fn refresh_info() -> TokenSecretInfo {
+        TokenSecretInfo {
+            keys: TokenKeys::hmac(b"secret"),
+            expiration: 3600,
+            remember_me_expiration: 86400,
+            leeway: 60,
+        }
+    }
+
+    fn activation_info(expiration: i64) -> TokenSecretInfo {
+        TokenSecretInfo {
+            keys: TokenKeys::hmac(b"activation"),
+            expiration,
+            remember_me_expiration: expiration,
+            leeway: 5,
+        }
+    }
+
+    #[test]
+    fn test_activation_token_expiry() {
+        let valid = activation_info(600);
+        let token = valid.generate_token(&user_info(false)).unwrap();
+        assert_eq!(valid.parse_token(&token).unwrap().uid, AccountId(1));
+
+        // Within the leeway.
+        let info = activation_info(-3);
+        let token = info.generate_token(&user_info(false)).unwrap();
+        assert!(info.parse_token(&token).is_ok());
+
+        // Past the leeway, which an access token's default would still allow.
+        let expired = activation_info(-30);
+        let token = expired.generate_token(&user_info(false)).unwrap();
+        assert!(matches!(
+            expired.parse_token(&token),
+            Err(AuthError(AuthInnerError::TokenExpired))
+        ));
+    }
+
+    #[test]
+    fn test_expired_and_malformed_tokens_differ() {
+        let code = |token: &str| {
+            let err = activation_info(600).parse_token(token).unwrap_err();
+            AppError::select_status_code(&err)
+        };
+
+        let expired = activation_info(-30)
+            .generate_token(&user_info(false))
+            .unwrap();
+        assert_eq!(code(&expired), (StatusCode::UNAUTHORIZED, 10011));
+        assert_eq!(code("garbage"), (StatusCode::UNAUTHORIZED, 10003));
+
+        // The signature is checked before the expiry.
+        let forged = TokenSecretInfo {
+            keys: TokenKeys::hmac(b"forged"),
+            ..activation_info(-30)
+        }
+        .generate_token(&user_info(false))
+        .unwrap();
+        assert_eq!(code(&forged), (StatusCode::UNAUTHORIZED, 10003));
+    }
+
+    fn user_info(remember_me: bool) -> UserInfo {
+        UserInfo {
+            uid: AccountId(1),
+            email: "test@test.com".to_string(),
+            status: AccountStatus::Active,
+            remember_me,
+            epoch: 0,
+            language: Language::EnUs,
+        }
+    }
+
+    fn account(status: AccountStatus) -> Account {
+        Account {
+            id: AccountId(1),
+            name: "Test User".to_string(),
+            email: "test@test.com".to_string(),
+            password: String::new(),
+            status,
+            language: Language::EnUs,
+            created_at: NaiveDateTime::default(),
+            updated_at: None,
+            deleted_at: None,
+            last_login_at: None,
+            password_changed_at: None,
+        }
+    }
+
+    #[test]
+    fn test_refresh_rejects_suspended_account() {
+        assert!(matches!(
+            Claims::ensure_not_suspended(&account(AccountStatus::Suspend)),
+            Err(AuthError(AuthInnerError::AccountSuspended))
+        ));
+        for status in [AccountStatus::Active, AccountStatus::Inactive] {
+            assert!(Claims::ensure_not_suspended(&account(status)).is_ok());
+        }
+    }
+
+    #[test]
+    fn test_refresh_rejects_token_predating_password_change() {
+        let info = refresh_info();
+        let token = info.generate_token(&user_info(false)).unwrap();
+        let claims = info.parse_token(&token).unwrap();
+        let issued_at =
+            chrono::DateTime::from_timestamp(claims.iat as i64, 0).unwrap();
+
+        let mut user = account(AccountStatus::Active);
+        assert!(claims.ensure_issued_after_password_change(&user).is_ok());
+
+        user.password_changed_at = Some(issued_at.naive_utc());
+        assert!(claims.ensure_issued_after_password_change(&user).is_ok());
+
+        user.password_changed_at =
+            Some((issued_at + chrono::Duration::seconds(1)).naive_utc());
+        assert!(matches!(
+            claims.ensure_issued_after_password_change(&user),
+            Err(AuthError(AuthInnerError::InvalidToken))
+        ));
+    }
+
+    fn rsa_keys() -> TokenKeys {
+        TokenKeys::rsa(
+            include_bytes!("../../../fixtures/jwt/test_rs256_private.pem"),
+            include_bytes!("../../../fixtures/jwt/test_rs256_public.pem"),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_sign_and_verify_per_algorithm() {
+        for keys in [TokenKeys::hmac(b"secret"), rsa_keys()] {
+            let algorithm = keys.algorithm;
+            let info = TokenSecretInfo {
+                keys,
+                ..refresh_info()
+            };
+            let token = info.generate_token(&user_info(false)).unwrap();
+
+            let header = jsonwebtoken::decode_header(&token).unwrap();
+            assert_eq!(header.alg, algorithm);
+            assert_eq!(info.parse_token(&token).unwrap().uid, AccountId(1));
+        }
+    }
+
+    #[test]
+    fn test_rejects_token_of_other_algorithm() {
+        let hmac = refresh_info();
+        let rsa = TokenSecretInfo {
+            keys: rsa_keys(),
+            ..refresh_info()
+        };
+
+        let token = hmac.generate_token(&user_info(false)).unwrap();
+        assert!(rsa.parse_token(&token).is_err());
+        let token = rsa.generate_token(&user_info(false)).unwrap();
+        assert!(hmac.parse_token(&token).is_err());
+    }
+
+    #[test]
+    fn test_signing_failure_is_internal_error() {
+        // An HMAC key can't sign RS256 tokens.
+        let info = TokenSecretInfo {
+            keys: TokenKeys {
+                algorithm: Algorithm::RS256,
+                ..TokenKeys::hmac(b"secret")
+            },
+            ..refresh_info()
+        };
+        let err = info.generate_token(&user_info(false)).unwrap_err();
+        assert!(matches!(err, AuthError(AuthInnerError::TokenCreation)));
+        assert_eq!(
+            AppError::select_status_code(&err),
+            (StatusCode::INTERNAL_SERVER_ERROR, 10002)
+        );
+    }
+
+    #[test]
+    fn test_rs256_requires_key_files() {
+        let cfg = JWTConfig {
+            alg: JwtAlgorithm::RS256,
+            ..Default::default()
+        };
+        assert!(TokenKeys::from_config(&cfg).is_err());
+    }
+
+    #[test]
+    fn test_tokens_have_unique_jti() {
+        let info = refresh_info();
+        let parse = |token: String| info.parse_token(&token).unwrap().jti;
+
+        let first = parse(info.generate_token(&user_info(false)).unwrap());
+        let second = parse(info.generate_token(&user_info(false)).unwrap());
+        assert!(!first.is_empty());
+        assert_ne!(first, second);
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn test_revoked_token_is_rejected() {
+        cfg::init("./fixtures/config.toml");
+        let mut redis = Redisor::init().get_redis().await.unwrap();
+        let info = refresh_info();
+        let parse = |token: String| info.parse_token(&token).unwrap();
+
+        let revoked = parse(info.generate_token(&user_info(false)).unwrap());
+        let other = parse(info.generate_token(&user_info(false)).unwrap());
+        Claims::revoke(&revoked.jti, revoked.exp, &mut redis)
+            .await
+            .unwrap();
+
+        assert!(matches!(
+            revoked.ensure_not_revoked(&mut redis).await,
+            Err(AuthError(AuthInnerError::InvalidToken))
+        ));
+        assert!(other.ensure_not_revoked(&mut redis).await.is_ok());
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn test_invalidated_sessions_are_rejected() {
+        cfg::init("./fixtures/config.toml");
+        let mut redis = Redisor::init().get_redis().await.unwrap();
+        let user = account(AccountStatus::Active);
+        let parse = |token: &str| {
+            Claims::parse_token(token, TokenType::ACCESS, true).unwrap()
+        };
+
+        let before =
+            Claims::generate_tokens_for_user(&user, false, None, &mut redis)
+                .await
+                .unwrap();
+        let before = parse(&before.access_token);
+        assert!(before.ensure_not_revoked(&mut redis).await.is_ok());
+
+        Claims::invalidate_sessions(user.id, &mut redis)
+            .await
+            .unwrap();
+        assert!(matches!(
+            before.ensure_not_revoked(&mut redis).await,
+            Err(AuthError(AuthInnerError::InvalidToken))
+        ));
+
+        let after =
+            Claims::generate_tokens_for_user(&user, false, None, &mut redis)
+                .await
+                .unwrap();
+        let after = parse(&after.access_token);
+        assert_eq!(after.epoch, before.epoch + 1);
+        assert!(after.ensure_not_revoked(&mut redis).await.is_ok());
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn test_batch_invalidation() {
+        cfg::init("./fixtures/config.toml");
+        let mut redis = Redisor::init().get_redis().await.unwrap();
+        let mut claims = Vec::new();
+        for id in [11, 12, 13] {
+            let user = Account {
+                id: AccountId(id),
+                ..account(AccountStatus::Active)
+            };
+            let tokens = Claims::generate_tokens_for_user(
+                &user, false, None, &mut redis,
+            )
+            .await
+            .unwrap();
+            claims.push(
+                Claims::parse_token(
+                    &tokens.access_token,
+                    TokenType::ACCESS,
+                    true,
+                )
+                .unwrap(),
+            );
+        }
+
+        Claims::invalidate_sessions_of(
+            &[AccountId(11), AccountId(12)],
+            &mut redis,
+        )
+        .await
+        .unwrap();
+        for revoked in &claims[..2] {
+            assert!(matches!(
+                revoked.ensure_not_revoked(&mut redis).await,
+                Err(AuthError(AuthInnerError::InvalidToken))
+            ));
+        }
+        assert!(claims[2].ensure_not_revoked(&mut redis).await.is_ok());
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn test_refresh_token_rotation() {
+        cfg::init("./fixtures/config.toml");
+        let mut redis = Redisor::init().get_redis().await.unwrap();
+        let info = refresh_info();
+        let user = account(AccountStatus::Active);
+        let epoch = Claims::token_epoch(user.id, &mut redis).await.unwrap();
+        let credential = UserInfo {
+            epoch,
+            ..user_info(false)
+        };
+
+        let first = info.claims(&credential);
+        first.activate(None, &mut redis).await.unwrap();
+
+        // Happy path, the chain moves on to the new token.
+        let second = info.claims(&credential);
+        first
+            .rotate(&second, None, false, &mut redis)
+            .await
+            .unwrap();
+        assert!(second.ensure_not_revoked(&mut redis).await.is_ok());
+
+        // Reusing the rotated out token revokes the whole chain.
+        let stolen = info.claims(&credential);
+        assert!(matches!(
+            first.rotate(&stolen, None, false, &mut redis).await,
+            Err(AuthError(AuthInnerError::InvalidToken))
+        ));
+        assert!(second.ensure_not_revoked(&mut redis).await.is_err());
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn test_refresh_token_bound_to_device() {
+        cfg::init("./fixtures/config.toml");
+        let mut redis = Redisor::init().get_redis().await.unwrap();
+        let info = refresh_info();
+        let user = account(AccountStatus::Active);
+        let epoch = Claims::token_epoch(user.id, &mut redis).await.unwrap();
+        let credential = UserInfo {
+            epoch,
+            ..user_info(false)
+        };
+
+        let first = info.claims(&credential);
+        first.activate(Some("phone"), &mut redis).await.unwrap();
+
+        // Refreshing from the same device moves the chain on.
+        let second = info.claims(&credential);
+        first
+            .rotate(&second, Some("phone"), true, &mut redis)
+            .await
+            .unwrap();
+        assert!(second.ensure_not_revoked(&mut redis).await.is_ok());
+
+        // From another one revokes every token.
+        let stolen = info.claims(&credential);
+        assert!(matches!(
+            second
+                .rotate(&stolen, Some("laptop"), true, &mut redis)
+                .await,
+            Err(AuthError(AuthInnerError::InvalidToken))
+        ));
+        assert!(second.ensure_not_revoked(&mut redis).await.is_err());
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn test_activation_token_single_use() {
+        cfg::init("./fixtures/config.toml");
+        let mut redis = Redisor::init().get_redis().await.unwrap();
+        let info = activation_info(600);
+        let claims = info
+            .parse_token(&info.generate_token(&user_info(false)).unwrap())
+            .unwrap();
+
+        claims.redeem(info.leeway, &mut redis).await.unwrap();
+        assert!(matches!(
+            claims.redeem(info.leeway, &mut redis).await,
+            Err(AuthError(AuthInnerError::InvalidToken))
+        ));
+    }
+
+    #[test]
+    fn test_remember_me_extends_expiration() {
+        let info = refresh_info();
+
+        let normal = info
+            .parse_token(&info.generate_token(&user_info(false)).unwrap())
+            .unwrap();
+        let remembered = info
+            .parse_token(&info.generate_token(&user_info(true)).unwrap())
+            .unwrap();
 
-        Claims::generate_tokens_for_user(&user).await
+        assert!(!normal.remember_me);
+        assert!(remembered.remember_me);
+        assert_eq!(normal.exp - normal.iat, 3600);
+        assert_eq!(remembered.exp - remembered.iat, 86400);
     }
 }
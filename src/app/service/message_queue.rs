@@ -1,23 +1,47 @@
-use std::sync::Arc;
+use std::{
+    future::Future,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
 
 use super::Service;
 use crate::{
-    app::bootstrap::{
-        constants::{MQ_SEND_EMAIL_QUEUE, MQ_SEND_EMAIL_TAG},
-        AppState,
+    app::{
+        bootstrap::{
+            constants::{
+                MQ_EVENT_EXCHANGE, MQ_SEND_EMAIL_DLX, MQ_SEND_EMAIL_QUEUE,
+                MQ_SEND_EMAIL_TAG, MQ_WELCOME_EMAIL_QUEUE,
+                MQ_WELCOME_EMAIL_TAG,
+            },
+            AppState,
+        },
+        entity::event::{Event, UserRegistered},
+        service::email_template::welcome_content,
+    },
+    library::{
+        cfg,
+        error::{ApiInnerError, AppResult},
+        mailor::Email,
+        mqer::{Interrupted, Subscriber},
+        Mqer,
     },
-    library::{error::AppResult, mailor::Email, mqer::Subscriber, Mqer},
 };
 
 #[derive(Clone)]
 pub struct Server {
     pub mqer: Arc<Mqer>,
+    /// Paces the sends of the email consumer, per `mail.send_rate`.
+    send_limiter: Option<Arc<TokenBucket>>,
 }
 
 impl Service for Server {
     async fn init() -> Server {
         Server {
             mqer: Arc::new(Mqer::init()),
+            send_limiter: cfg::config()
+                .mail
+                .send_rate
+                .map(|rate| Arc::new(TokenBucket::new(rate))),
         }
     }
 
@@ -28,11 +52,26 @@ impl Service for Server {
                 tracing::error!("Error occurred while sending email: {}", e)
             }
         };
+        if cfg::config().app.publish_events {
+            if let Err(e) = self.welcome_sender().await {
+                tracing::error!(
+                    "Error occurred while sending welcome email: {}",
+                    e
+                );
+            }
+        }
     }
 
     async fn shutdown(&self) {
-        match self.mqer.graceful_shutdown() {
-            Ok(()) => {}
+        match self.mqer.graceful_shutdown().await {
+            Ok(summary) if summary.timed_out > 0 => {
+                tracing::warn!(
+                    "Forced MQ shutdown, {} of {} in-flight messages dropped",
+                    summary.timed_out,
+                    summary.in_flight
+                );
+            }
+            Ok(_) => {}
             Err(e) => {
                 tracing::error!("Error occurred while closing MQ: {}", e)
             }
@@ -43,26 +82,342 @@ impl Service for Server {
 impl Server {
     pub async fn email_sender(&self) -> AppResult<()> {
         tracing::debug!("email customer started");
-        let func = |message: String| {
-            let result = serde_json::from_str::<Email>(&message)
-                .map_err(|e| {
-                    tracing::error!("Failed to parse email from message: {}", e)
-                })
-                .and_then(|email| {
-                    let res = email.sync_send_text().map_err(|e| {
-                        tracing::error!("Failed to send email: {}", e)
-                    });
-                    tracing::debug!("received:{:#?}", email);
-                    res
-                });
-            if result.is_err() {
-                tracing::error!("Failed to send email")
+        let server = self.clone();
+        let func = move |message: String| {
+            let server = server.clone();
+            async move {
+                let email =
+                    serde_json::from_str::<Email>(&message).map_err(|e| {
+                        anyhow::anyhow!(
+                            "Failed to parse email from message: {}",
+                            e
+                        )
+                    })?;
+                tracing::debug!("received:{:#?}", email);
+                let mail = cfg::config().mail.clone();
+                let sent = retry_with_backoff(
+                    mail.retries,
+                    Duration::from_millis(mail.retry_base_delay_ms),
+                    |delay| server.mqer.sleep_unless_stopped(delay),
+                    || async {
+                        if !server.throttle().await {
+                            return Ok(false);
+                        }
+                        email.async_send_text().await.map(|_| true)
+                    },
+                )
+                .await
+                .map_err(|e| anyhow::anyhow!("Failed to send email: {}", e))?;
+                if sent != Some(true) {
+                    return Err(Interrupted.into());
+                }
+                Ok(())
+            }
+        };
+        let mail = &cfg::config().mail;
+        let delegate = Subscriber::new_async(func, self.mqer.clone())
+            .with_redeliveries(
+                mail.max_redeliveries,
+                Duration::from_millis(mail.redelivery_delay_ms),
+            );
+        Ok(self
+            .mqer
+            .basic_receive_with_dlq(
+                MQ_SEND_EMAIL_QUEUE,
+                MQ_SEND_EMAIL_DLX,
+                MQ_SEND_EMAIL_TAG,
+                delegate,
+            )
+            .await?)
+    }
+
+    /// Queues `email` for [`Server::email_sender`], which retries, paces
+    /// and dead-letters the sends, once it fits the configured lengths.
+    pub async fn enqueue_email(&self, email: &Email<'_>) -> AppResult<()> {
+        email.validate_lengths().map_err(ApiInnerError::from)?;
+        let payload = serde_json::to_string(email).map_err(|e| {
+            anyhow::anyhow!("Error occurred while sending email: {}", e)
+        })?;
+        self.mqer
+            .basic_send_with_dlq(
+                MQ_SEND_EMAIL_QUEUE,
+                MQ_SEND_EMAIL_DLX,
+                &payload,
+            )
+            .await?;
+        Ok(())
+    }
+
+    /// Greets every newly registered account, independent of activation,
+    /// through the email queue.
+    pub async fn welcome_sender(&self) -> AppResult<()> {
+        tracing::debug!("welcome email customer started");
+        let server = self.clone();
+        let func = move |message: String| {
+            let server = server.clone();
+            async move {
+                let event = serde_json::from_str::<UserRegistered>(&message)
+                    .map_err(|e| {
+                        anyhow::anyhow!(
+                            "Failed to parse event from message: {}",
+                            e
+                        )
+                    })?;
+                let (subject, body) = welcome_content(event.language);
+                server
+                    .enqueue_email(&Email::new(&event.email, subject, body))
+                    .await
+                    .map_err(|e| {
+                        anyhow::anyhow!("Failed to queue welcome email: {}", e)
+                    })?;
+                Ok(())
             }
         };
-        let delegate = Subscriber::new(func, self.mqer.clone());
+        let delegate = Subscriber::new_async(func, self.mqer.clone());
         Ok(self
             .mqer
-            .basic_receive(MQ_SEND_EMAIL_QUEUE, MQ_SEND_EMAIL_TAG, delegate)
+            .topic_receive(
+                MQ_EVENT_EXCHANGE,
+                MQ_WELCOME_EMAIL_QUEUE,
+                UserRegistered::ROUTING_KEY,
+                MQ_WELCOME_EMAIL_TAG,
+                delegate,
+            )
             .await?)
     }
+
+    /// Waits for the turn of a send under `mail.send_rate`, returning false
+    /// when a shutdown interrupted the wait.
+    async fn throttle(&self) -> bool {
+        match &self.send_limiter {
+            Some(limiter) => {
+                self.mqer
+                    .sleep_unless_stopped(limiter.reserve(Instant::now()))
+                    .await
+            }
+            None => true,
+        }
+    }
+}
+
+/// Token bucket holding at most one token, refilled at `rate` tokens per
+/// second, which spaces sends evenly at `1 / rate` seconds.
+struct TokenBucket {
+    rate: f64,
+    state: Mutex<(f64, Instant)>,
+}
+
+impl TokenBucket {
+    fn new(rate: u32) -> Self {
+        Self {
+            rate: f64::from(rate.max(1)),
+            state: Mutex::new((1.0, Instant::now())),
+        }
+    }
+
+    /// Takes a token and returns how long to wait until it's available.
+    /// Tokens may be taken in advance, each caller then waits for its own.
+    fn reserve(&self, now: Instant) -> Duration {
+        let mut state = self
+            .state
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+        let (tokens, last) = &mut *state;
+        let elapsed = now.saturating_duration_since(*last).as_secs_f64();
+        *tokens = (*tokens + elapsed * self.rate).min(1.0) - 1.0;
+        *last = now.max(*last);
+        if *tokens >= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(-*tokens / self.rate)
+        }
+    }
+}
+
+/// Calls `f` until it succeeds, at most `retries` more times after the first
+/// failure, sleeping `base_delay * 2^n` before the n-th retry. Returns the
+/// last error once retries are exhausted, and `None` once `sleep` returns
+/// false, as a shutdown cut it short.
+async fn retry_with_backoff<T, E: std::fmt::Display, Fut, Sleep>(
+    retries: u32,
+    base_delay: Duration,
+    sleep: impl Fn(Duration) -> Sleep,
+    mut f: impl FnMut() -> Fut,
+) -> Result<Option<T>, E>
+where
+    Fut: Future<Output = Result<T, E>>,
+    Sleep: Future<Output = bool>,
+{
+    let mut attempt = 0;
+    loop {
+        match f().await {
+            Ok(value) => return Ok(Some(value)),
+            Err(e) if attempt < retries => {
+                let delay = base_delay.saturating_mul(1 << attempt.min(16));
+                tracing::warn!(
+                    "Attempt {} failed: {}, retrying in {:?}",
+                    attempt + 1,
+                    e,
+                    delay
+                );
+                if !sleep(delay).await {
+                    return Ok(None);
+                }
+                attempt += 1;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_token_bucket_caps_send_rate() {
+        let bucket = TokenBucket::new(10);
+        let now = Instant::now();
+        let waits = (0..50).map(|_| bucket.reserve(now)).collect::<Vec<_>>();
+
+        let within_first_second = waits
+            .iter()
+            .filter(|w| **w < Duration::from_secs(1))
+            .count();
+        assert_eq!(within_first_second, 10);
+        for pair in waits.windows(2) {
+            let gap = pair[1] - pair[0];
+            assert!(gap >= Duration::from_millis(99), "{gap:?}");
+        }
+
+        // Idle time refills at most one token.
+        let later = now + Duration::from_secs(60);
+        assert_eq!(bucket.reserve(later), Duration::ZERO);
+        assert!(bucket.reserve(later) > Duration::ZERO);
+    }
+
+    async fn sleep(delay: Duration) -> bool {
+        tokio::time::sleep(delay).await;
+        true
+    }
+
+    #[tokio::test]
+    async fn test_retry_with_backoff() {
+        let mut calls = 0;
+        let result =
+            retry_with_backoff(3, Duration::from_millis(1), sleep, || {
+                calls += 1;
+                std::future::ready(if calls <= 2 {
+                    Err("smtp hiccup")
+                } else {
+                    Ok(calls)
+                })
+            })
+            .await;
+        assert_eq!(result, Ok(Some(3)));
+
+        let mut calls = 0;
+        let result = retry_with_backoff(2, Duration::ZERO, sleep, || {
+            calls += 1;
+            std::future::ready(Err::<(), _>("smtp down"))
+        })
+        .await;
+        assert_eq!(result, Err("smtp down"));
+        assert_eq!(calls, 3);
+    }
+
+    #[tokio::test]
+    async fn test_retry_backoff_yields_to_runtime() {
+        // Runs on the same thread, only if the backoff doesn't block it.
+        let ticked = Arc::new(std::sync::atomic::AtomicBool::new(false));
+        let ticker = ticked.clone();
+        tokio::spawn(async move {
+            ticker.store(true, std::sync::atomic::Ordering::SeqCst);
+        });
+
+        let result =
+            retry_with_backoff(1, Duration::from_millis(50), sleep, || {
+                std::future::ready(Err::<(), _>("smtp down"))
+            })
+            .await;
+        assert!(result.is_err());
+        assert!(ticked.load(std::sync::atomic::Ordering::SeqCst));
+    }
+
+    #[tokio::test]
+    async fn test_retry_backoff_interrupted_by_shutdown() {
+        let mqer = Mqer::from_url("amqp://127.0.0.1:1".to_string());
+        mqer.graceful_shutdown().await.unwrap();
+
+        let mut calls = 0;
+        let start = Instant::now();
+        let result = retry_with_backoff(
+            3,
+            Duration::from_secs(60),
+            |delay| mqer.sleep_unless_stopped(delay),
+            || {
+                calls += 1;
+                std::future::ready(Err::<(), _>("smtp down"))
+            },
+        )
+        .await;
+        assert_eq!(result, Ok(None));
+        assert_eq!(calls, 1);
+        assert!(start.elapsed() < Duration::from_secs(1));
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn test_welcome_email_queued_on_user_registered() {
+        cfg::init("./fixtures/config.toml");
+        let server = Server::init().await;
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let sink = received.clone();
+        let delegate = Subscriber::new(
+            move |message| {
+                sink.lock().unwrap().push(message);
+                Ok(())
+            },
+            server.mqer.clone(),
+        );
+        server
+            .mqer
+            .basic_receive_with_dlq(
+                MQ_SEND_EMAIL_QUEUE,
+                MQ_SEND_EMAIL_DLX,
+                "app.dev.welcome_capture_tag",
+                delegate,
+            )
+            .await
+            .unwrap();
+        server.welcome_sender().await.unwrap();
+
+        let email = format!("{}@tuta.io", uuid::Uuid::new_v4());
+        let event = serde_json::json!({
+            "uid": 6192889942050345985_i64,
+            "email": email,
+            "language": "fr-FR",
+        });
+        server
+            .mqer
+            .topic_send(
+                MQ_EVENT_EXCHANGE,
+                UserRegistered::ROUTING_KEY,
+                &event.to_string(),
+            )
+            .await
+            .unwrap();
+        tokio::time::sleep(Duration::from_secs(1)).await;
+
+        let received = received.lock().unwrap().clone();
+        let queued = received
+            .iter()
+            .map(|message| serde_json::from_str::<Email>(message).unwrap())
+            .find(|queued| queued.to == email.as_str())
+            .unwrap();
+        let (subject, body) =
+            welcome_content(crate::models::types::Language::FrFr);
+        assert_eq!(queued.subject, subject);
+        assert_eq!(queued.body, body);
+    }
 }
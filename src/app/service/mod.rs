@@ -2,6 +2,8 @@ use std::sync::Arc;
 
 use crate::app::bootstrap::AppState;
 
+pub mod admin_guard;
+pub mod email_template;
 pub mod jwt_service;
 pub mod message_queue;
 
@@ -1,4 +1,4 @@
-use std::path::PathBuf;
+use std::{env, ffi::OsString, path::PathBuf, process};
 
 use clap::{Parser, Subcommand};
 
@@ -7,6 +7,10 @@ use crate::{
     library::{cfg, logger},
 };
 
+/// Names the config file used when `--config` isn't given.
+const CONFIG_ENV: &str = "IWI_CONFIG";
+const DEFAULT_CONFIG: &str = "./fixtures/config.toml";
+
 #[derive(Parser)]
 #[command(version, about, long_about = None)]
 struct Cli {
@@ -33,14 +37,26 @@ enum Commands {
 pub async fn cmd() {
     let cli = Cli::parse();
 
-    if let Some(config_path) = cli.config.as_deref() {
-        cfg::init(&config_path.to_string_lossy().to_string());
-    } else {
-        println!("loading default config file!!!!");
-        cfg::init(&"./fixtures/config.toml".to_string());
+    let defaulted = cli.config.is_none();
+    let config_path = cli
+        .config
+        .unwrap_or_else(|| default_config_path(env::var_os(CONFIG_ENV)));
+    if !config_path.is_file() {
+        eprintln!(
+            "💥 Config file `{}` not found, pass --config or set {CONFIG_ENV}",
+            config_path.display()
+        );
+        process::exit(1);
     }
+    cfg::init(&config_path.to_string_lossy());
 
-    let (_guard1, _guard2, _guard3, _guard4) = logger::init(cfg::config());
+    let _guards = logger::init(&cfg::config());
+    if defaulted {
+        tracing::warn!(
+            path = %config_path.display(),
+            "No --config given, loaded the default config file"
+        );
+    }
 
     #[allow(clippy::single_match)]
     match &cli.command {
@@ -58,3 +74,30 @@ pub async fn cmd() {
         None => {}
     }
 }
+
+/// The config file named by `env_value`, the value of [`CONFIG_ENV`], or
+/// [`DEFAULT_CONFIG`].
+fn default_config_path(env_value: Option<OsString>) -> PathBuf {
+    env_value
+        .filter(|value| !value.is_empty())
+        .map_or_else(|| PathBuf::from(DEFAULT_CONFIG), PathBuf::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_config_path_env_override() {
+        assert_eq!(
+            default_config_path(Some("/etc/iwi/config.toml".into())),
+            PathBuf::from("/etc/iwi/config.toml")
+        );
+        for unset in [None, Some(OsString::new())] {
+            assert_eq!(
+                default_config_path(unset),
+                PathBuf::from(DEFAULT_CONFIG)
+            );
+        }
+    }
+}
@@ -1,16 +1,60 @@
+use std::sync::OnceLock;
+
 use anyhow::anyhow;
 use argon2::{
-    password_hash::SaltString, Argon2, PasswordHash, PasswordHasher,
-    PasswordVerifier,
+    password_hash::{self, SaltString},
+    Algorithm, Argon2, Params, PasswordHash, PasswordHasher, PasswordVerifier,
+    Version,
 };
 use rand::{distributions::Alphanumeric, Rng};
 use rand_core::OsRng;
 
-use crate::library::error::{AppError, AppResult};
+use crate::library::{
+    cfg::{self, Argon2Config},
+    error::{
+        AppError::{self, AuthError},
+        AppResult, AuthInnerError,
+    },
+};
+
+// A hash with the configured parameters, verified against when there is no
+// real hash so that a rejection costs as much as a genuine attempt.
+static DUMMY_HASH: OnceLock<String> = OnceLock::new();
+
+fn dummy_hash() -> &'static str {
+    DUMMY_HASH.get_or_init(|| {
+        hash_password(b"dummy").unwrap_or_else(|e| {
+            panic!("💥 Failed to hash the dummy password: {e}")
+        })
+    })
+}
+
+/// Argon2id with the configured `app.argon2` costs, the defaults when the
+/// config isn't loaded.
+fn argon2() -> AppResult<Argon2<'static>> {
+    let costs = cfg::try_config()
+        .map_or_else(Argon2Config::default, |cfg| cfg.app.argon2);
+    argon2_with(&costs)
+}
+
+fn argon2_with(costs: &Argon2Config) -> AppResult<Argon2<'static>> {
+    let params = Params::new(costs.m_cost, costs.t_cost, costs.p_cost, None)
+        .map_err(|e| {
+            AppError::Anyhow(anyhow!("Invalid Argon2 parameters: {}", e))
+        })?;
+    Ok(Argon2::new(Algorithm::Argon2id, Version::V0x13, params))
+}
 
 pub fn hash_password(password: &[u8]) -> AppResult<String> {
+    hash_password_with(&argon2()?, password)
+}
+
+fn hash_password_with(
+    argon2: &Argon2<'_>,
+    password: &[u8],
+) -> AppResult<String> {
     let salt = SaltString::generate(&mut OsRng);
-    Argon2::default()
+    argon2
         .hash_password(password, &salt)
         .map_err(|e| {
             AppError::Anyhow(anyhow!("Error while hashing password: {}", e))
@@ -18,15 +62,37 @@ pub fn hash_password(password: &[u8]) -> AppResult<String> {
         .map(|hash| hash.to_string())
 }
 
+/// Whether `hashed` (the password given) matches the stored hash `input`.
+/// A stored hash that can't be verified against is logged as corrupt, then
+/// treated as a mismatch.
 pub fn verify_password(input: &str, hashed: &str) -> AppResult<bool> {
-    Ok(match PasswordHash::new(input) {
-        Ok(parsed_hash) => Argon2::default()
-            .verify_password(hashed.as_bytes(), &parsed_hash)
-            .map_or(false, |()| true),
-        Err(_) => false,
+    let argon2 = argon2()?;
+    let verified = PasswordHash::new(input).and_then(|parsed_hash| {
+        argon2.verify_password(hashed.as_bytes(), &parsed_hash)
+    });
+    Ok(match verified {
+        Ok(()) => true,
+        Err(password_hash::Error::Password) => false,
+        Err(e) => {
+            tracing::error!(
+                "Malformed stored password hash of {} bytes: {e}",
+                input.len()
+            );
+            false
+        }
     })
 }
 
+/// Rejects empty or whitespace-only passwords before they reach a stored
+/// hash, still spending one verification to avoid a timing oracle.
+pub fn reject_blank_password(password: &str) -> AppResult<()> {
+    if password.trim().is_empty() {
+        let _ = verify_password(dummy_hash(), password);
+        return Err(AuthError(AuthInnerError::WrongCredentials));
+    }
+    Ok(())
+}
+
 pub fn random_words(length: usize) -> String {
     rand::thread_rng()
         .sample_iter(&Alphanumeric)
@@ -34,3 +100,122 @@ pub fn random_words(length: usize) -> String {
         .map(char::from)
         .collect()
 }
+
+// Uppercase letters and digits without the easily confused 0/O, 1/I and L.
+pub const CODE_ALPHABET: &[u8] = b"23456789ABCDEFGHJKMNPQRSTUVWXYZ";
+
+/// Length of the codes emailed to users.
+pub const CODE_LENGTH: usize = 6;
+
+/// A code for users to type in from an email, drawn from an alphabet
+/// without look-alike characters.
+pub fn random_code(length: usize) -> String {
+    let mut rng = OsRng;
+    (0..length)
+        .map(|_| {
+            char::from(CODE_ALPHABET[rng.gen_range(0..CODE_ALPHABET.len())])
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io,
+        sync::{Arc, Mutex},
+        time::Instant,
+    };
+
+    use super::*;
+
+    #[derive(Clone, Default)]
+    struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+    impl io::Write for Buffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_malformed_stored_hash_logged() {
+        let buffer = Buffer::default();
+        let writer = buffer.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .with_writer(move || writer.clone())
+            .finish();
+        let logged = || String::from_utf8(buffer.0.lock().unwrap().clone());
+
+        tracing::subscriber::with_default(subscriber, || {
+            let hash = hash_password(b"password").unwrap();
+            assert!(!verify_password(&hash, "wrong").unwrap());
+            assert_eq!(logged().unwrap(), "");
+
+            // Still plain rejections, whether malformed or oversized.
+            assert!(!verify_password("garbage", "password").unwrap());
+            let oversized = format!("{hash}{}", "A".repeat(4096));
+            assert!(!verify_password(&oversized, "password").unwrap());
+        });
+        let logs = logged().unwrap();
+        assert_eq!(logs.matches("Malformed stored password hash").count(), 2);
+        assert!(logs.contains("ERROR"), "{logs}");
+        assert!(!logs.contains("garbage"), "{logs}");
+    }
+
+    #[test]
+    fn test_reject_blank_password() {
+        for password in ["", "   ", "\t\n"] {
+            assert!(matches!(
+                reject_blank_password(password),
+                Err(AuthError(AuthInnerError::WrongCredentials))
+            ));
+        }
+        assert!(reject_blank_password("password").is_ok());
+    }
+
+    #[test]
+    fn test_reject_blank_password_timing() {
+        let hash = dummy_hash();
+        let start = Instant::now();
+        let _ = verify_password(hash, "password");
+        let genuine = start.elapsed();
+
+        let start = Instant::now();
+        let _ = reject_blank_password("");
+        let blank = start.elapsed();
+
+        // Best-effort: the blank path must not be a cheap early return.
+        assert!(blank * 2 > genuine, "{blank:?} vs {genuine:?}");
+    }
+
+    #[test]
+    fn test_hash_with_custom_params() {
+        let costs = Argon2Config {
+            m_cost: 1024,
+            t_cost: 1,
+            p_cost: 1,
+        };
+        let hash =
+            hash_password_with(&argon2_with(&costs).unwrap(), b"password")
+                .unwrap();
+        assert!(hash.contains("m=1024,t=1,p=1"), "{hash}");
+        assert!(verify_password(&hash, "password").unwrap());
+        assert!(!verify_password(&hash, "wrong").unwrap());
+    }
+
+    #[test]
+    fn test_random_code_is_unambiguous() {
+        for length in [0, 6, 64] {
+            let code = random_code(length);
+            assert_eq!(code.chars().count(), length);
+            assert!(!code.contains(['0', 'O', '1', 'I', 'l', 'L']), "{code}");
+            assert!(code
+                .chars()
+                .all(|c| c.is_ascii_uppercase() || c.is_ascii_digit()));
+        }
+    }
+}
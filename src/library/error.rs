@@ -1,9 +1,14 @@
+use std::time::Duration;
+
 use axum::{
-    http::StatusCode,
+    http::{header::RETRY_AFTER, HeaderValue, StatusCode},
     response::{IntoResponse, Response},
 };
+use serde_json::{json, Value};
 use thiserror::Error;
 
+use crate::library::cfg::{self, EnvelopeVersion};
+
 pub type InnerResult<T> = Result<T, AppInnerError>;
 
 #[derive(Error, Debug)]
@@ -19,6 +24,8 @@ pub enum AppInnerError {
     JsonError(#[from] serde_json::Error),
     #[error("Email error: `{0}`")]
     EmailError(#[from] lettre::transport::smtp::Error),
+    #[error("Multiple rows in `{0}` for a unique lookup")]
+    DuplicateRows(&'static str),
     #[error("Internal server error")]
     Unknown(String),
     #[error(transparent)]
@@ -31,6 +38,10 @@ pub enum RedisorError {
     PoolError(#[from] deadpool_redis::PoolError),
     #[error("Redis execution error: `{0}`")]
     ExeError(#[from] deadpool_redis::redis::RedisError),
+    #[error("Redis TTL must be at least one second, got {0:?}")]
+    InvalidTtl(Duration),
+    #[error("Redis value (de)serialization error: `{0}`")]
+    SerdeError(#[from] serde_json::Error),
 }
 
 #[derive(Error, Debug)]
@@ -39,6 +50,10 @@ pub enum MqerError {
     PoolError(#[from] deadpool_lapin::PoolError),
     #[error("Mq execution error: `{0}`")]
     ExeError(#[from] deadpool_lapin::lapin::Error),
+    #[error("Mq publish not confirmed within {0:?}")]
+    ConfirmTimeout(Duration),
+    #[error("Mq publish rejected by the broker")]
+    Nacked,
 }
 
 #[derive(Error, Debug)]
@@ -49,8 +64,31 @@ pub enum ApiInnerError {
     #[error(transparent)]
     AxumFormRejection(#[from] axum::extract::rejection::FormRejection),
 
-    #[error("Verification Code Interval Not Satisfied")]
-    CodeIntervalRejection,
+    #[error("Account Quota Exceeded")]
+    AccountQuotaExceeded,
+
+    /// Any "slow down" rejection: rate limits, lockouts and resend
+    /// intervals, all answered the same way with a retry hint.
+    #[error("Too Many Requests, retry after {retry_after_secs}s")]
+    TooManyRequests { retry_after_secs: u64 },
+
+    #[error("Unknown field: `{0}`")]
+    UnknownField(String),
+
+    #[error("Weak password: {0}")]
+    WeakPassword(&'static str),
+}
+
+impl ApiInnerError {
+    /// A [`ApiInnerError::TooManyRequests`] retryable after `retry_after`,
+    /// rounded up to whole seconds.
+    pub fn too_many_requests(retry_after: Duration) -> Self {
+        let secs =
+            retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0);
+        Self::TooManyRequests {
+            retry_after_secs: secs.max(1),
+        }
+    }
 }
 
 #[derive(Error, Debug)]
@@ -94,6 +132,12 @@ pub enum AuthInnerError {
     InvalidTokenType,
     #[error("UserAlreadyActivated")]
     UserAlreadyActivated,
+    #[error("PermissionDenied")]
+    PermissionDenied,
+    #[error("TokenExpired")]
+    TokenExpired,
+    #[error("NameAlreadyTaken")]
+    NameAlreadyTaken,
 }
 
 impl AppError {
@@ -103,7 +147,10 @@ impl AppError {
                 AuthInnerError::WrongCredentials => {
                     (StatusCode::UNAUTHORIZED, 10001)
                 }
-                AuthInnerError::TokenCreation => (StatusCode::FORBIDDEN, 10002),
+                // A signing failure is a server misconfiguration.
+                AuthInnerError::TokenCreation => {
+                    (StatusCode::INTERNAL_SERVER_ERROR, 10002)
+                }
                 AuthInnerError::InvalidToken => {
                     (StatusCode::UNAUTHORIZED, 10003)
                 }
@@ -123,6 +170,15 @@ impl AppError {
                 AuthInnerError::UserAlreadyActivated => {
                     (StatusCode::CONFLICT, 10009)
                 }
+                AuthInnerError::PermissionDenied => {
+                    (StatusCode::FORBIDDEN, 10010)
+                }
+                AuthInnerError::TokenExpired => {
+                    (StatusCode::UNAUTHORIZED, 10011)
+                }
+                AuthInnerError::NameAlreadyTaken => {
+                    (StatusCode::CONFLICT, 10012)
+                }
             },
             Self::ApiError(e) => match e {
                 ApiInnerError::ValidationError(_) => {
@@ -131,7 +187,18 @@ impl AppError {
                 ApiInnerError::AxumFormRejection(_) => {
                     (StatusCode::UNPROCESSABLE_ENTITY, 20001)
                 }
-                ApiInnerError::CodeIntervalRejection => (StatusCode::OK, 30001),
+                ApiInnerError::AccountQuotaExceeded => {
+                    (StatusCode::FORBIDDEN, 20002)
+                }
+                ApiInnerError::TooManyRequests { .. } => {
+                    (StatusCode::TOO_MANY_REQUESTS, 30002)
+                }
+                ApiInnerError::UnknownField(_) => {
+                    (StatusCode::BAD_REQUEST, 20003)
+                }
+                ApiInnerError::WeakPassword(_) => {
+                    (StatusCode::UNPROCESSABLE_ENTITY, 20004)
+                }
             },
             _ => (StatusCode::BAD_REQUEST, 99999),
         }
@@ -143,10 +210,129 @@ pub type AppResult<T> = Result<T, AppError>;
 impl IntoResponse for AppError {
     fn into_response(self) -> Response {
         let (status, code) = Self::select_status_code(&self);
-        let body = axum::Json(serde_json::json!({
-            "code": code,
-            "msg": format!("{self}")
-        }));
-        (status, body).into_response()
+        let retry_after = match &self {
+            Self::ApiError(ApiInnerError::TooManyRequests {
+                retry_after_secs,
+            }) => Some(*retry_after_secs),
+            _ => None,
+        };
+        let details =
+            retry_after.map(|secs| json!({ "retry_after_secs": secs }));
+        let body = axum::Json(envelope(
+            envelope_version(),
+            code,
+            &format!("{self}"),
+            details,
+        ));
+        let mut response = (status, body).into_response();
+        if let Some(secs) = retry_after {
+            response
+                .headers_mut()
+                .insert(RETRY_AFTER, HeaderValue::from(secs));
+        }
+        response
+    }
+}
+
+fn envelope_version() -> EnvelopeVersion {
+    cfg::try_config()
+        .map_or_else(EnvelopeVersion::default, |cfg| cfg.app.envelope)
+}
+
+/// Wraps successful response data in the configured envelope.
+pub fn success_envelope(msg: &str, data: Option<Value>) -> Value {
+    envelope(envelope_version(), 0, msg, data)
+}
+
+/// Wraps an error code and message in the configured envelope.
+pub fn error_envelope(code: u32, msg: &str) -> Value {
+    envelope(envelope_version(), code, msg, None)
+}
+
+fn envelope(
+    version: EnvelopeVersion,
+    code: u32,
+    msg: &str,
+    data: Option<Value>,
+) -> Value {
+    match version {
+        EnvelopeVersion::V1 if code == 0 => {
+            json!({ "code": code, "msg": msg, "data": data })
+        }
+        // Errors only carry data as details, such as a retry hint.
+        EnvelopeVersion::V1 => match data {
+            Some(data) => json!({ "code": code, "msg": msg, "data": data }),
+            None => json!({ "code": code, "msg": msg }),
+        },
+        EnvelopeVersion::V2 if code == 0 => {
+            json!({ "success": true, "error": null, "result": data })
+        }
+        EnvelopeVersion::V2 => match data {
+            Some(data) => json!({
+                "success": false,
+                "error": { "code": code, "msg": msg, "data": data },
+                "result": null,
+            }),
+            None => json!({
+                "success": false,
+                "error": { "code": code, "msg": msg },
+                "result": null,
+            }),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_envelope_v1() {
+        assert_eq!(
+            envelope(EnvelopeVersion::V1, 0, "success", Some(json!(1))),
+            json!({ "code": 0, "msg": "success", "data": 1 })
+        );
+        assert_eq!(
+            envelope(EnvelopeVersion::V1, 10001, "Wrong credentials", None),
+            json!({ "code": 10001, "msg": "Wrong credentials" })
+        );
+    }
+
+    #[test]
+    fn test_envelope_v2() {
+        assert_eq!(
+            envelope(EnvelopeVersion::V2, 0, "success", Some(json!(1))),
+            json!({ "success": true, "error": null, "result": 1 })
+        );
+        assert_eq!(
+            envelope(EnvelopeVersion::V2, 10001, "Wrong credentials", None),
+            json!({
+                "success": false,
+                "error": { "code": 10001, "msg": "Wrong credentials" },
+                "result": null,
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_too_many_requests_response() {
+        // Code intervals hint the cooldown left, rounded up.
+        let error =
+            ApiInnerError::too_many_requests(Duration::from_millis(29_500));
+        let response = AppError::ApiError(error).into_response();
+
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(response.headers()[RETRY_AFTER], "30");
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(
+            serde_json::from_slice::<Value>(&body).unwrap(),
+            json!({
+                "code": 30002,
+                "msg": "Too Many Requests, retry after 30s",
+                "data": { "retry_after_secs": 30 },
+            })
+        );
     }
 }
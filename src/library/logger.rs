@@ -1,9 +1,14 @@
-use std::{str::FromStr, sync::Arc};
+use std::{
+    collections::HashMap,
+    fs, io,
+    path::Path,
+    str::FromStr,
+    sync::{Arc, OnceLock},
+};
 
+use arc_swap::ArcSwap;
 use chrono::Local;
-use tracing::{
-    level_filters::LevelFilter, subscriber::set_global_default, Level,
-};
+use tracing::{level_filters::LevelFilter, subscriber::set_global_default};
 use tracing_appender::non_blocking::{NonBlocking, WorkerGuard};
 use tracing_subscriber::{
     filter, fmt,
@@ -12,7 +17,51 @@ use tracing_subscriber::{
     Layer, Registry,
 };
 
-use crate::library::cfg::Config;
+use crate::library::cfg::{Config, LogConfig, LogRoute};
+
+// Levels are kept behind an `ArcSwap` so they can be changed on config
+// reload without rebuilding the subscriber.
+static LEVELS: OnceLock<ArcSwap<Levels>> = OnceLock::new();
+
+#[derive(Debug, Clone, Copy)]
+struct Levels {
+    mine_formatting: LevelFilter,
+    other_formatting: LevelFilter,
+    file: LevelFilter,
+}
+
+impl Levels {
+    fn from_config(cfg: &LogConfig) -> Self {
+        Self {
+            mine_formatting: LevelFilter::from_str(&cfg.mine_formatting_level)
+                .unwrap_or(LevelFilter::INFO),
+            other_formatting: LevelFilter::from_str(
+                &cfg.other_formatting_level,
+            )
+            .unwrap_or(LevelFilter::INFO),
+            file: LevelFilter::from_str(&cfg.file_level)
+                .unwrap_or(LevelFilter::INFO),
+        }
+    }
+}
+
+fn levels() -> Levels {
+    LEVELS.get().map_or_else(
+        || Levels {
+            mine_formatting: LevelFilter::INFO,
+            other_formatting: LevelFilter::INFO,
+            file: LevelFilter::INFO,
+        },
+        |levels| **levels.load(),
+    )
+}
+
+/// Applies the log levels of `cfg` to the running subscriber.
+pub fn reload(cfg: &Config) {
+    if let Some(levels) = LEVELS.get() {
+        levels.store(Arc::new(Levels::from_config(&cfg.log)));
+    }
+}
 
 struct LocalTimer;
 
@@ -25,13 +74,35 @@ impl FormatTime for LocalTimer {
 pub trait LogLayer<S: tracing::Subscriber>: Layer<S> + Send + Sync {}
 impl<S: tracing::Subscriber, L: Layer<S> + Send + Sync> LogLayer<S> for L {}
 
+struct Route<S> {
+    target_prefix: String,
+    min_level: LevelFilter,
+    layer: Box<dyn LogLayer<S>>,
+}
+
+/// Hands each event to the layer of the first matching [`LogRoute`], events
+/// matching none are dropped.
 struct RouterLayer<S> {
-    mine_layer: Box<dyn LogLayer<S>>,
-    database_layer: Box<dyn LogLayer<S>>,
-    other_layer: Box<dyn LogLayer<S>>,
-    error_layer: Box<dyn LogLayer<S>>,
-    mine_target: String,
-    database_target: String,
+    routes: Vec<Route<S>>,
+}
+
+impl<S> RouterLayer<S> {
+    /// Builds the layer of each route with `layer_for(file)`.
+    fn new(
+        routes: &[LogRoute],
+        mut layer_for: impl FnMut(&str) -> Box<dyn LogLayer<S>>,
+    ) -> Self {
+        let routes = routes
+            .iter()
+            .map(|route| Route {
+                target_prefix: route.target_prefix.clone(),
+                min_level: LevelFilter::from_str(&route.min_level)
+                    .unwrap_or(LevelFilter::TRACE),
+                layer: layer_for(&route.file),
+            })
+            .collect();
+        Self { routes }
+    }
 }
 
 impl<S> Layer<S> for RouterLayer<S>
@@ -44,60 +115,53 @@ where
         event: &tracing::Event<'_>,
         ctx: tracing_subscriber::layer::Context<'_, S>,
     ) {
-        match (event.metadata().level(), event.metadata().target()) {
-            (level, _) if level <= &Level::ERROR => {
-                self.error_layer.on_event(event, ctx)
-            }
-            (_, target) if target.starts_with(&self.mine_target) => {
-                self.mine_layer.on_event(event, ctx);
-            }
-            (_, target) if target.starts_with(&self.database_target) => {
-                self.database_layer.on_event(event, ctx);
-            }
-            _ => {
-                self.other_layer.on_event(event, ctx);
-            }
+        let metadata = event.metadata();
+        if let Some(route) = self.routes.iter().find(|route| {
+            metadata.level() <= &route.min_level
+                && metadata.target().starts_with(&route.target_prefix)
+        }) {
+            route.layer.on_event(event, ctx);
         }
     }
 }
 
-pub fn init(
-    cfg: &Config,
-) -> (WorkerGuard, WorkerGuard, WorkerGuard, WorkerGuard) {
-    let (
-        (mine_non_blocking, mine_guard),
-        (database_non_blocking, database_guard),
-        (other_non_blocking, other_guard),
-        (error_non_blocking, error_guard),
-        stdout,
-    ) = {
-        let stdout = cfg.app.env == "dev";
-
-        let (mine_file, database_file, other_file, error_file) = (
-            &cfg.log.mine_file,
-            &cfg.log.database_file,
-            &cfg.log.other_file,
-            &cfg.log.error_file,
+/// Creates the log directory if missing and checks a file can be written
+/// to it, as the appenders would otherwise fail on every write.
+fn ensure_writable(path: &str) -> io::Result<()> {
+    fs::create_dir_all(path)?;
+    let probe = Path::new(path).join(".write_probe");
+    fs::write(&probe, b"")?;
+    fs::remove_file(probe)
+}
+
+/// Returns the guards flushing the log files, which have to be kept alive.
+///
+/// Falls back to logging to stderr only when the log directory isn't
+/// writable, instead of failing startup.
+pub fn init(cfg: &Config) -> Vec<WorkerGuard> {
+    let fallback = ensure_writable(&cfg.log.path).err();
+    if let Some(e) = &fallback {
+        eprintln!(
+            "⚠️ Log directory `{}` is not writable, logging to stderr only: {e}",
+            cfg.log.path
         );
-        let setup_appender = |file| {
-            tracing_appender::non_blocking(tracing_appender::rolling::daily(
-                &cfg.log.path,
-                file,
-            ))
-        };
+    }
+    let stdout = cfg.app.env == "dev" || fallback.is_some();
 
-        let mine_appender = setup_appender(mine_file);
-        let database_appender = setup_appender(database_file);
-        let other_appender = setup_appender(other_file);
-        let error_appender = setup_appender(error_file);
-
-        (
-            mine_appender,
-            database_appender,
-            other_appender,
-            error_appender,
-            stdout,
-        )
+    // Routes to the same file share its appender.
+    let mut appenders: HashMap<String, NonBlocking> = HashMap::new();
+    let mut guards = Vec::new();
+    let mut appender_for = |file: &str| {
+        appenders
+            .entry(file.to_string())
+            .or_insert_with(|| {
+                let (non_blocking, guard) = tracing_appender::non_blocking(
+                    tracing_appender::rolling::daily(&cfg.log.path, file),
+                );
+                guards.push(guard);
+                non_blocking
+            })
+            .clone()
     };
 
     let setup_layer = |non_blocking: NonBlocking| {
@@ -109,24 +173,30 @@ pub fn init(
             .flatten_event(true)
     };
 
-    let mine_target = &cfg.log.mine_target;
-    let database_target = &cfg.log.database_target;
+    let router_file_layer = fallback.is_none().then(|| {
+        RouterLayer::new(&cfg.log.routes(), |file| {
+            Box::new(setup_layer(appender_for(file)))
+        })
+    });
 
-    let router_file_layer = RouterLayer {
-        mine_layer: Box::new(setup_layer(mine_non_blocking)),
-        database_layer: Box::new(setup_layer(database_non_blocking)),
-        other_layer: Box::new(setup_layer(other_non_blocking)),
-        error_layer: Box::new(setup_layer(error_non_blocking)),
-        mine_target: mine_target.clone(),
-        database_target: database_target.clone(),
-    };
+    let initial = Levels::from_config(&cfg.log);
+    match LEVELS.get() {
+        Some(current) => current.store(Arc::new(initial)),
+        None => {
+            let _ = LEVELS.set(ArcSwap::from_pointee(initial));
+        }
+    }
 
     let (mine_level_formatting, other_level_formatting, level_file) = (
-        LevelFilter::from_str(&cfg.log.mine_formatting_level)
-            .unwrap_or(LevelFilter::INFO),
-        LevelFilter::from_str(&cfg.log.other_formatting_level)
-            .unwrap_or(LevelFilter::INFO),
-        LevelFilter::from_str(&cfg.log.file_level).unwrap_or(LevelFilter::INFO),
+        filter::dynamic_filter_fn(|metadata, _| {
+            metadata.level() <= &levels().mine_formatting
+        }),
+        filter::dynamic_filter_fn(|metadata, _| {
+            metadata.level() <= &levels().other_formatting
+        }),
+        filter::dynamic_filter_fn(|metadata, _| {
+            metadata.level() <= &levels().file
+        }),
     );
 
     if stdout {
@@ -170,5 +240,141 @@ pub fn init(
         });
     }
 
-    (mine_guard, database_guard, other_guard, error_guard)
+    guards
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io,
+        sync::{Arc, Mutex},
+    };
+
+    use tracing_subscriber::fmt::MakeWriter;
+
+    use super::*;
+
+    #[derive(Clone, Default)]
+    struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Buffer {
+        fn contents(&self) -> String {
+            String::from_utf8(self.0.lock().unwrap().clone()).unwrap()
+        }
+    }
+
+    impl io::Write for Buffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl<'a> MakeWriter<'a> for Buffer {
+        type Writer = Self;
+
+        fn make_writer(&'a self) -> Self::Writer {
+            self.clone()
+        }
+    }
+
+    #[test]
+    fn test_warn_routed_to_error_file_with_warn_threshold() {
+        let cfg = LogConfig {
+            path: "./logs".to_string(),
+            mine_formatting_level: "info".to_string(),
+            other_formatting_level: "info".to_string(),
+            mine_file: "mine.log".to_string(),
+            other_file: "other.log".to_string(),
+            database_file: "database.log".to_string(),
+            error_file: "error.log".to_string(),
+            error_level: "warn".to_string(),
+            file_level: "info".to_string(),
+            mine_target: "app".to_string(),
+            database_target: "sqlx".to_string(),
+            routes: vec![],
+        };
+        let files: HashMap<String, Buffer> = cfg
+            .routes()
+            .into_iter()
+            .map(|route| (route.file, Buffer::default()))
+            .collect();
+        let router = RouterLayer::new(&cfg.routes(), |file| {
+            Box::new(fmt::layer().with_writer(files[file].clone()))
+        });
+
+        tracing::subscriber::with_default(
+            Registry::default().with(router),
+            || {
+                tracing::warn!(target: "app::auth", "login failed");
+                tracing::info!(target: "app::auth", "login succeeded");
+            },
+        );
+
+        let errors = files["error.log"].contents();
+        assert!(errors.contains("login failed"));
+        assert!(!errors.contains("login succeeded"));
+        assert!(files["mine.log"].contents().contains("login succeeded"));
+    }
+
+    #[test]
+    fn test_events_follow_first_matching_route() {
+        let route =
+            |target_prefix: &str, file: &str, min_level: &str| LogRoute {
+                target_prefix: target_prefix.to_string(),
+                file: file.to_string(),
+                min_level: min_level.to_string(),
+            };
+        let routes = [
+            route("", "error.log", "error"),
+            route("app::auth", "auth.log", "info"),
+            route("app", "mine.log", "trace"),
+        ];
+        let files: HashMap<&str, Buffer> =
+            ["error.log", "auth.log", "mine.log"]
+                .into_iter()
+                .map(|file| (file, Buffer::default()))
+                .collect();
+        let router = RouterLayer::new(&routes, |file| {
+            Box::new(fmt::layer().with_writer(files[file].clone()))
+        });
+
+        tracing::subscriber::with_default(
+            Registry::default().with(router),
+            || {
+                tracing::info!(target: "app::auth::login", "signed in");
+                tracing::debug!(target: "app::auth::login", "too verbose");
+                tracing::error!(target: "app::auth::login", "failed");
+                tracing::info!(target: "app::api", "served");
+                tracing::info!(target: "sqlx", "unrouted");
+            },
+        );
+
+        let auth = files["auth.log"].contents();
+        assert!(auth.contains("signed in"));
+        assert!(!auth.contains("failed"));
+        let mine = files["mine.log"].contents();
+        assert!(mine.contains("too verbose") && mine.contains("served"));
+        assert!(files["error.log"].contents().contains("failed"));
+        assert!(files
+            .values()
+            .all(|file| !file.contents().contains("unrouted")));
+    }
+
+    #[test]
+    fn test_unwritable_log_dir_falls_back() {
+        let dir = tempfile::tempdir().unwrap();
+        let missing = dir.path().join("logs");
+        assert!(ensure_writable(missing.to_str().unwrap()).is_ok());
+        assert!(missing.is_dir());
+
+        // A directory can't be created below a regular file.
+        let file = dir.path().join("file");
+        fs::write(&file, b"").unwrap();
+        let unwritable = file.join("logs");
+        assert!(ensure_writable(unwritable.to_str().unwrap()).is_err());
+    }
 }
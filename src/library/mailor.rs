@@ -1,12 +1,13 @@
-use std::fmt::Debug;
+use std::{borrow::Cow, fmt::Debug};
 
 use lettre::{
-    message::header::ContentType,
+    message::{header::ContentType, MessageBuilder, MultiPart},
     transport::smtp::{authentication::Credentials, response::Response},
     AsyncSmtpTransport, AsyncTransport, Message, SmtpTransport, Tokio1Executor,
     Transport,
 };
 use serde::{Deserialize, Serialize};
+use validator::{ValidationError, ValidationErrors};
 
 use crate::library::{
     cfg,
@@ -14,12 +15,43 @@ use crate::library::{
     error::{AppInnerError, InnerResult},
 };
 
-// TODO: masking the password in the log using macro
+/// Format of [`Email::body`].
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum EmailContentType {
+    #[default]
+    Text,
+    Html,
+}
+
+impl From<EmailContentType> for ContentType {
+    fn from(content_type: EmailContentType) -> Self {
+        match content_type {
+            EmailContentType::Text => Self::TEXT_PLAIN,
+            EmailContentType::Html => Self::TEXT_HTML,
+        }
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Email<'a> {
-    pub to: &'a str,
-    pub subject: &'a str,
-    pub body: &'a str,
+    // Borrowed from the payload unless it has escapes, e.g. a multi-line
+    // body, which `&str` could not hold.
+    #[serde(borrow)]
+    pub to: Cow<'a, str>,
+    #[serde(borrow)]
+    pub subject: Cow<'a, str>,
+    #[serde(borrow)]
+    pub body: Cow<'a, str>,
+    // Payloads queued before this field existed are plain text.
+    #[serde(default)]
+    pub content_type: EmailContentType,
+    /// HTML alternative to the plaintext `body`, sent together with it as
+    /// `multipart/alternative`.
+    #[serde(default, borrow)]
+    pub html: Option<Cow<'a, str>>,
     pub config: MailConfig,
 }
 
@@ -27,25 +59,99 @@ impl<'a> Email<'a> {
     pub fn new(to: &'a str, subject: &'a str, body: &'a str) -> Self {
         let config = cfg::config().mail.clone();
         Self {
-            to,
-            subject,
-            body,
+            to: to.into(),
+            subject: subject.into(),
+            body: body.into(),
+            content_type: EmailContentType::Text,
+            html: None,
             config,
         }
     }
 
-    pub fn sync_send_text(&self) -> InnerResult<Response> {
-        let message = Message::builder()
+    pub fn new_html(to: &'a str, subject: &'a str, html_body: &'a str) -> Self {
+        Self {
+            content_type: EmailContentType::Html,
+            ..Self::new(to, subject, html_body)
+        }
+    }
+
+    pub fn new_multipart(
+        to: &'a str,
+        subject: &'a str,
+        text: &'a str,
+        html: &'a str,
+    ) -> Self {
+        Self {
+            html: Some(html.into()),
+            ..Self::new(to, subject, text)
+        }
+    }
+
+    /// Checks the subject and body against the `max_subject_length` and
+    /// `max_body_length` of the mail config.
+    pub fn validate_lengths(&self) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::new();
+        let mut check = |field, value: &str, max: usize| {
+            if value.len() > max {
+                let mut error = ValidationError::new("length");
+                error.add_param("max".into(), &max);
+                error.add_param("value".into(), &value.len());
+                errors.add(field, error);
+            }
+        };
+        check("subject", &self.subject, self.config.max_subject_length);
+        check("body", &self.body, self.config.max_body_length);
+        if let Some(html) = &self.html {
+            check("html", html, self.config.max_body_length);
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    fn builder(&self) -> InnerResult<MessageBuilder> {
+        Ok(Message::builder()
             .from(self.config.username.parse().map_err(|e| {
                 anyhow::anyhow!("Error occurred while sending message: {}", e)
             })?)
             .to(self.to.parse().map_err(|e| {
                 anyhow::anyhow!("Error occurred while sending message: {}", e)
             })?)
-            .subject(self.subject)
-            .header(ContentType::TEXT_PLAIN) // ContentType::TEXT_HTML
+            .subject(self.subject.as_ref()))
+    }
+
+    fn message(&self) -> InnerResult<Message> {
+        if let Some(html) = &self.html {
+            return self.multipart_message(&self.body, html);
+        }
+        Ok(self
+            .builder()?
+            .header(ContentType::from(self.content_type))
             .body(self.body.to_string())
-            .unwrap();
+            .unwrap())
+    }
+
+    fn multipart_message(
+        &self,
+        text: &str,
+        html: &str,
+    ) -> InnerResult<Message> {
+        Ok(self
+            .builder()?
+            .multipart(MultiPart::alternative_plain_html(
+                text.to_string(),
+                html.to_string(),
+            ))
+            .map_err(|e| {
+                anyhow::anyhow!("Error occurred while building message: {}", e)
+            })?)
+    }
+
+    pub fn sync_send_text(&self) -> InnerResult<Response> {
+        let message = self.message()?;
         let creds = Credentials::new(
             self.config.username.clone(),
             self.config.password.clone(),
@@ -61,31 +167,156 @@ impl<'a> Email<'a> {
     }
 
     pub async fn async_send_text(&self) -> InnerResult<Response> {
-        let message = Message::builder()
-            .from(self.config.username.parse().map_err(|e| {
-                anyhow::anyhow!("Error occurred while sending message: {}", e)
-            })?)
-            .to(self.to.parse().map_err(|e| {
-                anyhow::anyhow!("Error occurred while sending message: {}", e)
-            })?)
-            .subject(self.subject)
-            .header(ContentType::TEXT_PLAIN) // ContentType::TEXT_HTML
-            .body(self.body.to_string())
-            .unwrap();
+        let message = self.message()?;
+        Ok(self.async_mailer()?.send(message).await?)
+    }
+
+    /// Sends `text` and `html` as alternatives of the same content, letting
+    /// the client pick the richest one it supports.
+    pub async fn async_send_multipart(
+        &self,
+        text: &str,
+        html: &str,
+    ) -> InnerResult<Response> {
+        let message = self.multipart_message(text, html)?;
+        Ok(self.async_mailer()?.send(message).await?)
+    }
+
+    fn async_mailer(&self) -> InnerResult<AsyncSmtpTransport<Tokio1Executor>> {
         let creds = Credentials::new(
             self.config.username.clone(),
             self.config.password.clone(),
         );
 
-        let mailer =
+        Ok(
             AsyncSmtpTransport::<Tokio1Executor>::relay(&self.config.host)
                 .map_err(|e| {
                     tracing::error!("📧 Failed to send email: {e}");
                     AppInnerError::EmailError(e)
                 })?
                 .credentials(creds)
-                .build();
+                .build(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn email(content_type: EmailContentType) -> Email<'static> {
+        Email {
+            to: "vainjoker@tuta.io".into(),
+            subject: "Active your account".into(),
+            body: "<p>Active Code: 123456</p>".into(),
+            content_type,
+            html: None,
+            config: MailConfig {
+                username: "iwi@tuta.io".to_string(),
+                password: "password".to_string(),
+                password_file: None,
+                host: "mail.mail.ee".to_string(),
+                retries: 0,
+                retry_base_delay_ms: 0,
+                send_rate: None,
+                max_redeliveries: 0,
+                redelivery_delay_ms: 0,
+                max_subject_length: 32,
+                max_body_length: 64,
+            },
+        }
+    }
+
+    #[test]
+    fn test_content_type_round_trip() {
+        let json =
+            serde_json::to_string(&email(EmailContentType::Html)).unwrap();
+        let email = serde_json::from_str::<Email>(&json).unwrap();
+        assert_eq!(email.content_type, EmailContentType::Html);
+
+        let mut legacy = serde_json::to_value(&email).unwrap();
+        legacy.as_object_mut().unwrap().remove("content_type");
+        let legacy = legacy.to_string();
+        let email = serde_json::from_str::<Email>(&legacy).unwrap();
+        assert_eq!(email.content_type, EmailContentType::Text);
+    }
+
+    #[test]
+    fn test_escaped_body_round_trip() {
+        let mut email = email(EmailContentType::Text);
+        email.body = "Active Code: 123456\nOr click \"here\"".into();
+
+        let json = serde_json::to_string(&email).unwrap();
+        let parsed = serde_json::from_str::<Email>(&json).unwrap();
+        assert_eq!(parsed.body, email.body);
+    }
+
+    #[test]
+    fn test_message_content_type() {
+        for (content_type, header) in [
+            (EmailContentType::Text, "Content-Type: text/plain"),
+            (EmailContentType::Html, "Content-Type: text/html"),
+        ] {
+            let message = email(content_type).message().unwrap();
+            let formatted = String::from_utf8(message.formatted()).unwrap();
+            assert!(formatted.contains(header), "{formatted}");
+        }
+    }
+
+    #[test]
+    fn test_multipart_message() {
+        let mut email = email(EmailContentType::Text);
+        email.html = Some(
+            "<html>\n  <body style=\"font-family: sans-serif\">\n    <p>Active \
+             Code: <b>123456</b></p>\n  </body>\n</html>"
+                .into(),
+        );
+
+        let json = serde_json::to_string(&email).unwrap();
+        let email = serde_json::from_str::<Email>(&json).unwrap();
+        let message = email.message().unwrap();
+        let formatted = String::from_utf8(message.formatted()).unwrap();
+        for part in [
+            "multipart/alternative",
+            "Content-Type: text/plain",
+            "Content-Type: text/html",
+            "<b>123456</b>",
+            "font-family: sans-serif",
+        ] {
+            assert!(formatted.contains(part), "{part} missing in {formatted}");
+        }
+    }
+
+    #[test]
+    fn test_over_length_subject_rejected() {
+        let mut email = email(EmailContentType::Text);
+        assert!(email.validate_lengths().is_ok());
+
+        let subject = "s".repeat(33);
+        email.subject = subject.into();
+        let errors = email.validate_lengths().unwrap_err();
+        assert_eq!(
+            errors.field_errors().keys().collect::<Vec<_>>(),
+            [&"subject"]
+        );
+    }
+
+    #[test]
+    fn test_over_length_body_rejected() {
+        let mut email = email(EmailContentType::Text);
+        let body = "b".repeat(64);
+        email.body = body.into();
+        assert!(email.validate_lengths().is_ok());
+
+        let body = "b".repeat(65);
+        email.body = body.into();
+        let errors = email.validate_lengths().unwrap_err();
+        assert!(errors.field_errors().contains_key("body"));
 
-        Ok(mailer.send(message).await?)
+        email.body = "ok".into();
+        let html = "h".repeat(65);
+        email.html = Some(html.into());
+        let errors = email.validate_lengths().unwrap_err();
+        assert!(errors.field_errors().contains_key("html"));
     }
 }
@@ -6,6 +6,7 @@ pub mod logger;
 pub mod mailor;
 pub mod mqer;
 pub mod redisor;
+pub mod validation;
 
 pub use dber::{Dber, DB};
 pub use mqer::{Mqer, MQ};
@@ -1,28 +1,104 @@
+use std::{collections::HashMap, time::Duration};
+
 use deadpool_redis::{
-    redis::{AsyncCommands, FromRedisValue, ToRedisArgs},
+    redis::{self, AsyncCommands, FromRedisValue, ToRedisArgs},
     Connection, Pool, Runtime,
 };
+use serde::{de::DeserializeOwned, Serialize};
 
 use crate::library::{
     cfg,
     error::{InnerResult, RedisorError},
 };
 
+const SCAN_COUNT: usize = 100;
+
 pub struct Redisor {
     pub pool: Pool,
-    pub prefix: &'static str,
+    pub prefix: String,
 }
 
 pub struct Redis {
     pub connection: Connection,
-    pub prefix: &'static str,
+    pub prefix: String,
+    pool: Pool,
+}
+
+/// Applies the key prefix inside [`Redis::pipeline`].
+pub struct KeyPrefix<'a>(&'a str);
+
+impl KeyPrefix<'_> {
+    pub fn key(&self, key: &str) -> String {
+        format!("{}:{}", self.0, key)
+    }
+}
+
+/// Deletes the lock only while it still holds our token, so a lock that
+/// expired and was taken by someone else is left alone.
+const UNLOCK_SCRIPT: &str = r#"
+if redis.call("GET", KEYS[1]) == ARGV[1] then
+    return redis.call("DEL", KEYS[1])
+end
+return 0
+"#;
+
+/// A lock taken with [`Redis::try_lock`]. Released with [`LockGuard::unlock`]
+/// or, in the background, when dropped; it expires after its TTL either way.
+pub struct LockGuard {
+    pool: Pool,
+    key: String,
+    token: String,
+    released: bool,
+}
+
+impl LockGuard {
+    /// Releases the lock, returns whether we still held it.
+    pub async fn unlock(mut self) -> InnerResult<bool> {
+        self.released = true;
+        release(&self.pool, &self.key, &self.token).await
+    }
+}
+
+impl Drop for LockGuard {
+    fn drop(&mut self) {
+        if self.released {
+            return;
+        }
+        let Ok(handle) = tokio::runtime::Handle::try_current() else {
+            tracing::warn!("Lock {} left to expire, no runtime", self.key);
+            return;
+        };
+        let (pool, key, token) = (
+            self.pool.clone(),
+            std::mem::take(&mut self.key),
+            std::mem::take(&mut self.token),
+        );
+        handle.spawn(async move {
+            if let Err(e) = release(&pool, &key, &token).await {
+                tracing::warn!("Failed to release lock {key}: {e}");
+            }
+        });
+    }
+}
+
+async fn release(pool: &Pool, key: &str, token: &str) -> InnerResult<bool> {
+    let mut conn = pool.get().await.map_err(RedisorError::PoolError)?;
+    let deleted: i64 = redis::Script::new(UNLOCK_SCRIPT)
+        .key(key)
+        .arg(token)
+        .invoke_async(&mut conn)
+        .await
+        .map_err(RedisorError::ExeError)?;
+    Ok(deleted == 1)
 }
 
 impl Redisor {
     pub fn init() -> Self {
         let cfg = cfg::config();
-        let url = cfg.app.redis_url.clone();
-        let prefix = &cfg.app.redis_prefix;
+        Self::from_url(cfg.app.redis_url.clone(), cfg.app.redis_prefix.clone())
+    }
+
+    pub fn from_url(url: String, prefix: String) -> Self {
         let deadpool = deadpool_redis::Config::from_url(url);
         match deadpool.create_pool(Some(Runtime::Tokio1)) {
             Ok(pool) => {
@@ -37,7 +113,8 @@ impl Redisor {
 
     pub async fn get_redis(&self) -> InnerResult<Redis> {
         Ok(Redis {
-            prefix: self.prefix,
+            prefix: self.prefix.clone(),
+            pool: self.pool.clone(),
             connection: self
                 .pool
                 .get()
@@ -45,11 +122,36 @@ impl Redisor {
                 .map_err(RedisorError::PoolError)?,
         })
     }
+
+    /// Sends a `PING`, giving up after `timeout`.
+    pub async fn healthy(&self, timeout: Duration) -> bool {
+        let check = async {
+            let mut conn =
+                self.pool.get().await.map_err(RedisorError::PoolError)?;
+            redis::cmd("PING")
+                .query_async::<String>(&mut conn)
+                .await
+                .map_err(RedisorError::ExeError)?;
+            Ok::<_, RedisorError>(())
+        };
+
+        match tokio::time::timeout(timeout, check).await {
+            Ok(Ok(())) => true,
+            Ok(Err(e)) => {
+                tracing::warn!("Redis health check failed: {e}");
+                false
+            }
+            Err(_) => {
+                tracing::warn!("Redis health check timed out");
+                false
+            }
+        }
+    }
 }
 
 impl Redis {
     pub fn key(&mut self, key: &str) -> String {
-        format!("{}:{}", self.prefix, key)
+        KeyPrefix(&self.prefix).key(key)
     }
 
     pub async fn get<T: FromRedisValue + Send + Sync>(
@@ -105,30 +207,123 @@ impl Redis {
         Ok(())
     }
 
-    pub async fn del(&mut self, key: &str) -> InnerResult<()> {
+    /// Reads the whole hash, an absent key reads as an empty map.
+    pub async fn hgetall(
+        &mut self,
+        key: &str,
+    ) -> InnerResult<HashMap<String, String>> {
+        let key = self.key(key);
+        let result: HashMap<String, String> = self
+            .connection
+            .hgetall(key)
+            .await
+            .map_err(RedisorError::ExeError)?;
+        Ok(result)
+    }
+
+    pub async fn hget<T: FromRedisValue + Send + Sync>(
+        &mut self,
+        key: &str,
+        field: &str,
+    ) -> InnerResult<Option<T>> {
+        let key = self.key(key);
+        let result: Option<T> = self
+            .connection
+            .hget(key, field)
+            .await
+            .map_err(RedisorError::ExeError)?;
+        Ok(result)
+    }
+
+    pub async fn hdel(&mut self, key: &str, field: &str) -> InnerResult<()> {
         let key = self.key(key);
         self.connection
-            .del::<_, ()>(key)
+            .hdel::<_, _, ()>(key, field)
             .await
             .map_err(RedisorError::ExeError)?;
         Ok(())
     }
 
+    /// Returns whether `key` existed.
+    pub async fn del(&mut self, key: &str) -> InnerResult<bool> {
+        let key = self.key(key);
+        let deleted: i64 = self
+            .connection
+            .del(key)
+            .await
+            .map_err(RedisorError::ExeError)?;
+        Ok(deleted == 1)
+    }
+
     pub async fn set_ex<T: ToRedisArgs + Send + Sync>(
         &mut self,
         key: &str,
         value: T,
-        ttl: u64,
+        ttl: Duration,
     ) -> InnerResult<()> {
+        let ttl = ttl_secs(ttl)?;
         let key = self.key(key);
         self.connection
-            .set_ex::<_, _, ()>(key, value, ttl)
+            .set_ex::<_, _, ()>(key, value, ttl.unsigned_abs())
             .await
             .map_err(RedisorError::ExeError)?;
         Ok(())
     }
 
-    pub async fn expire(&mut self, key: &str, ttl: i64) -> InnerResult<()> {
+    /// Stores `value` as JSON, expiring after `ttl` when given.
+    pub async fn set_json<T: Serialize + Sync>(
+        &mut self,
+        key: &str,
+        value: &T,
+        ttl: Option<Duration>,
+    ) -> InnerResult<()> {
+        let json =
+            serde_json::to_string(value).map_err(RedisorError::SerdeError)?;
+        match ttl {
+            Some(ttl) => self.set_ex(key, json, ttl).await,
+            None => self.set(key, json).await,
+        }
+    }
+
+    /// Reads a value stored with [`Redis::set_json`].
+    pub async fn get_json<T: DeserializeOwned>(
+        &mut self,
+        key: &str,
+    ) -> InnerResult<Option<T>> {
+        self.get::<String>(key)
+            .await?
+            .map(|json| serde_json::from_str(&json))
+            .transpose()
+            .map_err(|e| RedisorError::SerdeError(e).into())
+    }
+
+    /// Sets `key` only if it doesn't exist yet, returns whether it was set.
+    pub async fn set_nx_ex<T: ToRedisArgs + Send + Sync>(
+        &mut self,
+        key: &str,
+        value: T,
+        ttl: Duration,
+    ) -> InnerResult<bool> {
+        let ttl = ttl_secs(ttl)?;
+        let key = self.key(key);
+        let result: Option<String> = redis::cmd("SET")
+            .arg(key)
+            .arg(value)
+            .arg("NX")
+            .arg("EX")
+            .arg(ttl)
+            .query_async(&mut self.connection)
+            .await
+            .map_err(RedisorError::ExeError)?;
+        Ok(result.is_some())
+    }
+
+    pub async fn expire(
+        &mut self,
+        key: &str,
+        ttl: Duration,
+    ) -> InnerResult<()> {
+        let ttl = ttl_secs(ttl)?;
         let key = self.key(key);
         self.connection
             .expire::<_, ()>(key, ttl)
@@ -137,46 +332,204 @@ impl Redis {
         Ok(())
     }
 
-    // pub async fn mget(
-    //     &mut self,
-    //     keys: &[&str],
-    // ) -> InnerResult<Vec<Option<String>>> {
-    //     // let key = self.key(key);
-    //     let result: Vec<Option<String>> = self
-    //         .connection
-    //         .mget(keys)
-    //         .await
-    //         .map_err(RedisorError::ExeError)?;
-    //     Ok(result)
-    // }
-
-    // pub async fn hgetalls(
-    //     &mut self,
-    //     keys: &[&str],
-    // ) -> InnerResult<Vec<HashMap<String, String>>> {
-    //     let mut pipe = redis::pipe();
-    //     keys.into_iter().for_each(|key| {
-    //         pipe.hgetall(key);
-    //     });
-    //     let result = pipe
-    //         .query_async(&mut self.connection)
-    //         .await
-    //         .map_err(RedisorError::ExeError)?;
-    //     Ok(result)
-    // }
-
-    // pub async fn hgets(
-    //     &mut self,
-    //     key: &str,
-    //     fields: &[&str],
-    // ) -> InnerResult<Vec<Option<String>>> {
-    //     let result: Vec<Option<String>> = self
-    //         .connection
-    //         .hget(key, fields)
-    //         .await
-    //         .map_err(RedisorError::ExeError)?;
-    //     Ok(result)
-    // }
+    /// The time left before `key` expires, `None` when it's missing or never
+    /// expires.
+    pub async fn ttl(&mut self, key: &str) -> InnerResult<Option<Duration>> {
+        let key = self.key(key);
+        let secs: i64 = self
+            .connection
+            .ttl(key)
+            .await
+            .map_err(RedisorError::ExeError)?;
+        Ok(u64::try_from(secs).ok().map(Duration::from_secs))
+    }
+
+    /// Increments `key` by `by` and returns the new value.
+    pub async fn incr(&mut self, key: &str, by: i64) -> InnerResult<i64> {
+        let key = self.key(key);
+        let result: i64 = self
+            .connection
+            .incr(key, by)
+            .await
+            .map_err(RedisorError::ExeError)?;
+        Ok(result)
+    }
+
+    /// Decrements `key` by `by` and returns the new value.
+    pub async fn decr(&mut self, key: &str, by: i64) -> InnerResult<i64> {
+        let key = self.key(key);
+        let result: i64 = self
+            .connection
+            .decr(key, by)
+            .await
+            .map_err(RedisorError::ExeError)?;
+        Ok(result)
+    }
+
+    /// Like [`Redis::incr`], but the first increment sets the expiry, later
+    /// ones leave it untouched.
+    pub async fn incr_ex(
+        &mut self,
+        key: &str,
+        by: i64,
+        ttl: Duration,
+    ) -> InnerResult<i64> {
+        let ttl = ttl_secs(ttl)?;
+        let key = self.key(key);
+        let (value,): (i64,) = redis::pipe()
+            .atomic()
+            .incr(&key, by)
+            .cmd("EXPIRE")
+            .arg(&key)
+            .arg(ttl)
+            .arg("NX")
+            .ignore()
+            .query_async(&mut self.connection)
+            .await
+            .map_err(RedisorError::ExeError)?;
+        Ok(value)
+    }
+
+    /// Runs the commands added by `build` atomically in one round trip,
+    /// returning the results of those not marked `ignore()` as `T`.
+    ///
+    /// Keys are sent as given, unlike the other methods the pipeline can't
+    /// tell keys from other arguments. Apply the prefix with
+    /// [`KeyPrefix::key`]:
+    ///
+    /// ```ignore
+    /// let (value,): (String,) = redis
+    ///     .pipeline(|pipe, prefix| {
+    ///         pipe.set(prefix.key("a"), "1").ignore().get(prefix.key("a"));
+    ///     })
+    ///     .await?;
+    /// ```
+    pub async fn pipeline<T, F>(&mut self, build: F) -> InnerResult<T>
+    where
+        T: FromRedisValue,
+        F: FnOnce(&mut redis::Pipeline, &KeyPrefix),
+    {
+        let mut pipe = redis::pipe();
+        pipe.atomic();
+        build(&mut pipe, &KeyPrefix(&self.prefix));
+        let result: T = pipe
+            .query_async(&mut self.connection)
+            .await
+            .map_err(RedisorError::ExeError)?;
+        Ok(result)
+    }
+
+    /// Lists the keys matching the glob `pattern` under the prefix, with the
+    /// prefix stripped. Uses `SCAN` so Redis isn't blocked, which may return
+    /// a key more than once if it's modified meanwhile. Stops after `limit`
+    /// keys when given.
+    pub async fn scan_prefix(
+        &mut self,
+        pattern: &str,
+        limit: Option<usize>,
+    ) -> InnerResult<Vec<String>> {
+        let pattern = self.key(pattern);
+        let prefix = self.key("");
+        let mut keys = vec![];
+        let mut cursor = 0_u64;
+        loop {
+            let (next, batch): (u64, Vec<String>) = redis::cmd("SCAN")
+                .cursor_arg(cursor)
+                .arg("MATCH")
+                .arg(&pattern)
+                .arg("COUNT")
+                .arg(SCAN_COUNT)
+                .query_async(&mut self.connection)
+                .await
+                .map_err(RedisorError::ExeError)?;
+            keys.extend(batch.into_iter().map(|key| {
+                key.strip_prefix(&prefix)
+                    .map_or(key.clone(), str::to_string)
+            }));
+            if let Some(limit) = limit {
+                if keys.len() >= limit {
+                    keys.truncate(limit);
+                    break;
+                }
+            }
+            if next == 0 {
+                break;
+            }
+            cursor = next;
+        }
+        Ok(keys)
+    }
+
+    /// Gets several keys in one round trip, in the order of `keys`.
+    pub async fn mget<T: FromRedisValue + Send + Sync>(
+        &mut self,
+        keys: &[&str],
+    ) -> InnerResult<Vec<Option<T>>> {
+        if keys.is_empty() {
+            return Ok(vec![]);
+        }
+        let keys = keys.iter().map(|key| self.key(key)).collect::<Vec<_>>();
+        let result: Vec<Option<T>> = self
+            .connection
+            .mget(keys)
+            .await
+            .map_err(RedisorError::ExeError)?;
+        Ok(result)
+    }
+
+    pub async fn mset<T: ToRedisArgs + Send + Sync>(
+        &mut self,
+        pairs: &[(&str, T)],
+    ) -> InnerResult<()> {
+        if pairs.is_empty() {
+            return Ok(());
+        }
+        let pairs = pairs
+            .iter()
+            .map(|(key, value)| (self.key(key), value))
+            .collect::<Vec<_>>();
+        self.connection
+            .mset::<_, _, ()>(&pairs)
+            .await
+            .map_err(RedisorError::ExeError)?;
+        Ok(())
+    }
+
+    /// Takes the lock `key` for `ttl` unless someone else holds it, in which
+    /// case `None` is returned.
+    pub async fn try_lock(
+        &mut self,
+        key: &str,
+        ttl: Duration,
+    ) -> InnerResult<Option<LockGuard>> {
+        ttl_secs(ttl)?;
+        let key = self.key(key);
+        let token = uuid::Uuid::new_v4().to_string();
+        let result: Option<String> = redis::cmd("SET")
+            .arg(&key)
+            .arg(&token)
+            .arg("NX")
+            .arg("PX")
+            .arg(u64::try_from(ttl.as_millis()).unwrap_or(u64::MAX))
+            .query_async(&mut self.connection)
+            .await
+            .map_err(RedisorError::ExeError)?;
+        Ok(result.map(|_| LockGuard {
+            pool: self.pool.clone(),
+            key,
+            token,
+            released: false,
+        }))
+    }
+}
+
+/// Redis expiries are whole seconds, anything shorter than one second
+/// would expire immediately or not at all.
+fn ttl_secs(ttl: Duration) -> Result<i64, RedisorError> {
+    i64::try_from(ttl.as_secs())
+        .ok()
+        .filter(|secs| *secs > 0)
+        .ok_or(RedisorError::InvalidTtl(ttl))
 }
 
 #[cfg(test)]
@@ -189,7 +542,7 @@ mod tests {
     #[tokio::test]
     #[ignore]
     async fn test_redisor_init() {
-        cfg::init(&"./fixtures/config.toml".to_string());
+        cfg::init("./fixtures/config.toml");
         let redisor = Redisor::init();
         let mut redis = redisor.get_redis().await.unwrap();
 
@@ -200,7 +553,7 @@ mod tests {
     #[tokio::test]
     #[ignore]
     async fn test_redisor_del() {
-        cfg::init(&"./fixtures/config.toml".to_string());
+        cfg::init("./fixtures/config.toml");
         let redisor = Redisor::init();
         let mut redis = redisor.get_redis().await.unwrap();
 
@@ -209,19 +562,23 @@ mod tests {
             redis.get::<String>("key2").await.unwrap(),
             Some("value".to_string())
         );
-        redis.del("key2").await.unwrap();
+        assert!(redis.del("key2").await.unwrap());
         assert_eq!(redis.get::<String>("key2").await.unwrap(), None);
+        assert!(!redis.del("key2").await.unwrap());
         // redis.del("key2").await.unwrap();
     }
 
     #[tokio::test]
     #[ignore]
     async fn test_redisor_set_ex() {
-        cfg::init(&"./fixtures/config.toml".to_string());
+        cfg::init("./fixtures/config.toml");
         let redisor = Redisor::init();
         let mut redis = redisor.get_redis().await.unwrap();
         redis.del("key3").await.unwrap();
-        redis.set_ex("key3", "value", 10).await.unwrap();
+        redis
+            .set_ex("key3", "value", Duration::from_secs(10))
+            .await
+            .unwrap();
         assert_eq!(
             redis.get::<String>("key3").await.unwrap(),
             Some("value".to_string())
@@ -234,7 +591,7 @@ mod tests {
     #[tokio::test]
     #[ignore]
     async fn test_redisor_hset() {
-        cfg::init(&"./fixtures/config.toml".to_string());
+        cfg::init("./fixtures/config.toml");
         let redisor = Redisor::init();
         let mut redis = redisor.get_redis().await.unwrap();
         redis.del("key4").await.unwrap();
@@ -250,7 +607,7 @@ mod tests {
     #[tokio::test]
     #[ignore]
     async fn test_redisor_hkeys() {
-        cfg::init(&"./fixtures/config.toml".to_string());
+        cfg::init("./fixtures/config.toml");
         let redisor = Redisor::init();
         let mut redis = redisor.get_redis().await.unwrap();
         redis.del("key5").await.unwrap();
@@ -264,14 +621,55 @@ mod tests {
         redis.del("key5").await.unwrap();
     }
 
+    #[tokio::test]
+    #[ignore]
+    async fn test_redisor_hgetall() {
+        cfg::init("./fixtures/config.toml");
+        let redisor = Redisor::init();
+        let mut redis = redisor.get_redis().await.unwrap();
+        redis.del("key11").await.unwrap();
+        assert!(redis.hgetall("key11").await.unwrap().is_empty());
+        redis.hset("key11", "field1", "value1").await.unwrap();
+        redis.hset("key11", "field2", "value2").await.unwrap();
+        assert_eq!(
+            redis.hgetall("key11").await.unwrap(),
+            HashMap::from([
+                ("field1".to_string(), "value1".to_string()),
+                ("field2".to_string(), "value2".to_string()),
+            ])
+        );
+        redis.del("key11").await.unwrap();
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn test_redisor_hget_hdel() {
+        cfg::init("./fixtures/config.toml");
+        let redisor = Redisor::init();
+        let mut redis = redisor.get_redis().await.unwrap();
+        redis.del("key12").await.unwrap();
+        redis.hset("key12", "field1", "value1").await.unwrap();
+        assert_eq!(
+            redis.hget::<String>("key12", "field1").await.unwrap(),
+            Some("value1".to_string())
+        );
+        redis.hdel("key12", "field1").await.unwrap();
+        assert_eq!(
+            redis.hget::<String>("key12", "field1").await.unwrap(),
+            None
+        );
+        redis.del("key12").await.unwrap();
+    }
+
     #[tokio::test]
     #[ignore]
     async fn test_redisor_expire() {
-        cfg::init(&"./fixtures/config.toml".to_string());
+        cfg::init("./fixtures/config.toml");
         let redisor = Redisor::init();
         let mut redis = redisor.get_redis().await.unwrap();
         redis.del("key6").await.unwrap();
-        redis.set_ex("key6", "value", 10).await.unwrap();
+        redis.set("key6", "value").await.unwrap();
+        redis.expire("key6", Duration::from_secs(10)).await.unwrap();
         assert_eq!(
             redis.get::<String>("key6").await.unwrap(),
             Some("value".to_string())
@@ -281,62 +679,233 @@ mod tests {
         redis.del("key6").await.unwrap();
     }
 
-    // #[tokio::test]
-    // async fn test_redisor_mget() {
-    //     cfg::init(&"./fixtures/config.toml".to_string());
-    //     let redisor = Redisor::init();
-    //     let mut redis = redisor.get_redis().await.unwrap();
-    //     redis.set("key7", "value1").await.unwrap();
-    //     redis.set("key8", "value2").await.unwrap();
-    //     assert_eq!(
-    //         redis.mget(&["key7", "key8"].to_vec()).await.unwrap(),
-    //         vec![Some("value1".to_string()), Some("value2".to_string())]
-    //     );
-    //     redis.del("key7").await.unwrap();
-    //     redis.del("key8").await.unwrap();
-    // }
-
-    // #[tokio::test]
-    // async fn test_redisor_hget() {
-    //     cfg::init(&"./fixtures/config.toml".to_string());
-    //     let redisor = Redisor::init();
-    //     let mut redis = redisor.get_redis().await.unwrap();
-    //     redis.del("key9").await.unwrap();
-    //     redis.hset("key9", "field1", "value1").await.unwrap();
-    //     redis.hset("key9", "field2", "value2").await.unwrap();
-    //     assert_eq!(
-    //         redis.hgets("key9", &["field1", "field2"]).await.unwrap(),
-    //         vec![Some("value1".to_string()), Some("value2".to_string())]
-    //     );
-    //     redis.del("key9").await.unwrap();
-    // }
-
-    // #[tokio::test]
-    // async fn test_redisor_hgetalls() {
-    //     cfg::init(&"./fixtures/config.toml".to_string());
-    //     let redisor = Redisor::init();
-    //     let mut redis = redisor.get_redis().await.unwrap();
-    //     redis.del("key10").await.unwrap();
-    //     redis.hset("key10", "field1", "value1").await.unwrap();
-    //     redis.hset("key10", "field2", "value2").await.unwrap();
-    //     redis.del("key11").await.unwrap();
-    //     redis.hset("key11", "field1", "value1").await.unwrap();
-    //     redis.hset("key11", "field2", "value2").await.unwrap();
-    //     eprintln!(
-    //         "{:#?}",
-    //         redis.hgetalls(&["key10", "key11", "key12"]).await.unwrap()
-    //     );
-    //     let mut hm1 = HashMap::new();
-    //     hm1.insert("field1".to_string(), "value1".to_string());
-    //     hm1.insert("field2".to_string(), "value2".to_string());
-    //     let mut hm2 = HashMap::new();
-    //     hm2.insert("field1".to_string(), "value1".to_string());
-    //     hm2.insert("field2".to_string(), "value2".to_string());
-    //     assert_eq!(
-    //         redis.hgetalls(&["key10", "key11"]).await.unwrap(),
-    //         vec![hm1, hm2]
-    //     );
-    //     redis.del("key10").await.unwrap();
-    //     redis.del("key11").await.unwrap();
-    // }
+    #[tokio::test]
+    #[ignore]
+    async fn test_redisor_incr_decr() {
+        cfg::init("./fixtures/config.toml");
+        let redisor = Redisor::init();
+        let mut redis = redisor.get_redis().await.unwrap();
+        redis.del("key13").await.unwrap();
+        for _ in 0..3 {
+            redis.incr("key13", 1).await.unwrap();
+        }
+        assert_eq!(redis.get::<i64>("key13").await.unwrap(), Some(3));
+        assert_eq!(redis.decr("key13", 2).await.unwrap(), 1);
+        redis.del("key13").await.unwrap();
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn test_redisor_incr_ex() {
+        cfg::init("./fixtures/config.toml");
+        let redisor = Redisor::init();
+        let mut redis = redisor.get_redis().await.unwrap();
+        redis.del("key14").await.unwrap();
+        let ttl = Duration::from_secs(2);
+        for _ in 0..3 {
+            redis.incr_ex("key14", 1, ttl).await.unwrap();
+        }
+        assert_eq!(redis.get::<i64>("key14").await.unwrap(), Some(3));
+        tokio::time::sleep(time::Duration::from_millis(2500)).await;
+        assert_eq!(redis.get::<i64>("key14").await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn test_redisor_pipeline() {
+        cfg::init("./fixtures/config.toml");
+        let redisor = Redisor::init();
+        let mut redis = redisor.get_redis().await.unwrap();
+        let (value,): (String,) = redis
+            .pipeline(|pipe, prefix| {
+                pipe.set(prefix.key("key15"), "value15")
+                    .ignore()
+                    .set(prefix.key("key16"), "value16")
+                    .ignore()
+                    .get(prefix.key("key15"));
+            })
+            .await
+            .unwrap();
+        assert_eq!(value, "value15");
+        assert_eq!(
+            redis.get::<String>("key16").await.unwrap(),
+            Some("value16".to_string())
+        );
+        redis.del("key15").await.unwrap();
+        redis.del("key16").await.unwrap();
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn test_redisor_try_lock() {
+        cfg::init("./fixtures/config.toml");
+        let redisor = Redisor::init();
+        let mut redis = redisor.get_redis().await.unwrap();
+        let ttl = time::Duration::from_secs(10);
+
+        let guard = redis.try_lock("lock:key1", ttl).await.unwrap().unwrap();
+        assert!(redis.try_lock("lock:key1", ttl).await.unwrap().is_none());
+        assert!(guard.unlock().await.unwrap());
+
+        let guard = redis.try_lock("lock:key1", ttl).await.unwrap().unwrap();
+        drop(guard);
+        tokio::time::sleep(time::Duration::from_millis(100)).await;
+        let guard = redis.try_lock("lock:key1", ttl).await.unwrap().unwrap();
+
+        // A lock taken over after expiring isn't released by the old guard.
+        redis.set("lock:key1", "someone else").await.unwrap();
+        assert!(!guard.unlock().await.unwrap());
+        redis.del("lock:key1").await.unwrap();
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn test_redisor_json() {
+        #[derive(Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+        struct Session {
+            uid: i64,
+            email: String,
+        }
+
+        cfg::init("./fixtures/config.toml");
+        let redisor = Redisor::init();
+        let mut redis = redisor.get_redis().await.unwrap();
+
+        let session = Session {
+            uid: 1,
+            email: "test@example.com".to_string(),
+        };
+        redis
+            .set_json("key17", &session, Some(time::Duration::from_secs(10)))
+            .await
+            .unwrap();
+        assert_eq!(
+            redis.get_json::<Session>("key17").await.unwrap(),
+            Some(session)
+        );
+
+        redis.set("key17", "not json").await.unwrap();
+        assert!(redis.get_json::<Session>("key17").await.is_err());
+        redis.del("key17").await.unwrap();
+        assert_eq!(redis.get_json::<Session>("key17").await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn test_redisor_scan_prefix() {
+        cfg::init("./fixtures/config.toml");
+        let redisor = Redisor::init();
+        let mut redis = redisor.get_redis().await.unwrap();
+        let keys = ["scan:key1", "scan:key2", "scan:key3"];
+        for key in keys {
+            redis.set(key, "value").await.unwrap();
+        }
+        let mut scanned = redis.scan_prefix("scan:*", None).await.unwrap();
+        scanned.sort();
+        assert_eq!(scanned, keys);
+        assert_eq!(
+            redis.scan_prefix("scan:*", Some(2)).await.unwrap().len(),
+            2
+        );
+        for key in keys {
+            redis.del(key).await.unwrap();
+        }
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn test_redisor_mset_mget() {
+        cfg::init("./fixtures/config.toml");
+        let redisor = Redisor::init();
+        let mut redis = redisor.get_redis().await.unwrap();
+        redis.del("key8").await.unwrap();
+        redis.del("key9").await.unwrap();
+        redis.del("key10").await.unwrap();
+        redis
+            .mset(&[("key8", "value8"), ("key9", "value9")])
+            .await
+            .unwrap();
+        assert_eq!(
+            redis
+                .mget::<String>(&["key8", "key10", "key9"])
+                .await
+                .unwrap(),
+            vec![Some("value8".to_string()), None, Some("value9".to_string())]
+        );
+        assert!(redis.mget::<String>(&[]).await.unwrap().is_empty());
+        redis.del("key8").await.unwrap();
+        redis.del("key9").await.unwrap();
+    }
+
+    #[test]
+    fn test_ttl_secs_rejects_sub_second() {
+        for ttl in [Duration::ZERO, Duration::from_millis(999)] {
+            assert!(matches!(
+                ttl_secs(ttl),
+                Err(RedisorError::InvalidTtl(rejected)) if rejected == ttl
+            ));
+        }
+        assert_eq!(ttl_secs(Duration::from_secs(60)).unwrap(), 60);
+        assert!(ttl_secs(Duration::MAX).is_err());
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn test_redisor_set_nx_ex() {
+        cfg::init("./fixtures/config.toml");
+        let redisor = Redisor::init();
+        let mut redis = redisor.get_redis().await.unwrap();
+        redis.del("key7").await.unwrap();
+        let ttl = Duration::from_secs(10);
+        assert!(redis.set_nx_ex("key7", "first", ttl).await.unwrap());
+        assert!(!redis.set_nx_ex("key7", "second", ttl).await.unwrap());
+        assert_eq!(
+            redis.get::<String>("key7").await.unwrap(),
+            Some("first".to_string())
+        );
+        redis.del("key7").await.unwrap();
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn test_redisor_healthy() {
+        cfg::init("./fixtures/config.toml");
+        let redisor = Redisor::init();
+        assert!(redisor.healthy(Duration::from_secs(2)).await);
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn test_redisor_unhealthy() {
+        let redisor = Redisor::from_url(
+            "redis://127.0.0.1:1".to_string(),
+            "app".to_string(),
+        );
+        assert!(!redisor.healthy(Duration::from_secs(2)).await);
+    }
+
+    #[tokio::test]
+    async fn test_redisor_unresponsive_within_timeout() {
+        let addr = silent_server().await;
+        let redisor =
+            Redisor::from_url(format!("redis://{addr}"), "app".to_string());
+
+        let started = time::Instant::now();
+        assert!(!redisor.healthy(Duration::from_millis(200)).await);
+        assert!(started.elapsed() < Duration::from_secs(1));
+    }
+
+    /// Accepts connections and never answers.
+    async fn silent_server() -> std::net::SocketAddr {
+        let listener =
+            tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let mut sockets = Vec::new();
+            while let Ok((socket, _)) = listener.accept().await {
+                sockets.push(socket);
+            }
+        });
+        addr
+    }
 }
@@ -0,0 +1,152 @@
+use validator::ValidationError;
+
+use crate::library::{
+    cfg::{self, PasswordPolicyConfig},
+    crypto::{CODE_ALPHABET, CODE_LENGTH},
+    error::{
+        ApiInnerError,
+        AppError::{ApiError, AuthError},
+        AppResult, AuthInnerError,
+    },
+};
+
+/// The form emails are compared in: trimmed and lowercased.
+pub fn normalize_email(email: &str) -> String {
+    email.trim().to_lowercase()
+}
+
+/// Names log in like emails, so they must be typeable as shown: not blank,
+/// no surrounding whitespace nor control characters, at most 255
+/// characters.
+pub fn validate_name(name: &str) -> Result<(), ValidationError> {
+    let invalid =
+        |message| Err(ValidationError::new("name").with_message(message));
+    if name.trim().is_empty() {
+        return invalid("must not be blank".into());
+    }
+    if name.chars().count() > 255 {
+        return invalid("must be at most 255 characters".into());
+    }
+    if name.trim() != name {
+        return invalid("must not start or end with whitespace".into());
+    }
+    if name.chars().any(char::is_control) {
+        return invalid("must not contain control characters".into());
+    }
+    Ok(())
+}
+
+/// Rejects passwords not meeting the configured `app.password_policy`.
+pub fn validate_password_strength(password: &str) -> AppResult<()> {
+    check_password_strength(password, &cfg::config().app.password_policy)
+}
+
+fn check_password_strength(
+    password: &str,
+    policy: &PasswordPolicyConfig,
+) -> AppResult<()> {
+    let weak = |reason| Err(ApiError(ApiInnerError::WeakPassword(reason)));
+    if password.chars().count() < policy.min_length {
+        return weak("too short");
+    }
+    let has = |class: fn(&char) -> bool| password.chars().any(|c| class(&c));
+    if policy.require_lowercase && !has(char::is_ascii_lowercase) {
+        return weak("missing a lowercase letter");
+    }
+    if policy.require_uppercase && !has(char::is_ascii_uppercase) {
+        return weak("missing an uppercase letter");
+    }
+    if policy.require_digit && !has(char::is_ascii_digit) {
+        return weak("missing a digit");
+    }
+    if policy.require_symbol
+        && !has(|c| !c.is_alphanumeric() && !c.is_whitespace())
+    {
+        return weak("missing a symbol");
+    }
+    Ok(())
+}
+
+/// Returns an emailed code as issued, forgiving surrounding whitespace and
+/// lowercase. Anything [`crypto::random_code`](super::crypto::random_code)
+/// can't have produced is a `WrongCode`.
+pub fn validate_code(code: &str) -> AppResult<String> {
+    let code = code.trim().to_ascii_uppercase();
+    if code.len() != CODE_LENGTH
+        || !code.bytes().all(|b| CODE_ALPHABET.contains(&b))
+    {
+        return Err(AuthError(AuthInnerError::WrongCode));
+    }
+    Ok(code)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_email() {
+        for email in [
+            "vainjoker@tuta.io",
+            "  VainJoker@Tuta.IO ",
+            "\tVAINJOKER@TUTA.IO\n",
+        ] {
+            assert_eq!(normalize_email(email), "vainjoker@tuta.io");
+        }
+        assert_eq!(normalize_email(""), "");
+    }
+
+    #[test]
+    fn test_validate_name() {
+        for name in ["VJ", "Vain Joker", "虚无", &"n".repeat(255)] {
+            assert!(validate_name(name).is_ok(), "{name}");
+        }
+        for name in [
+            "",
+            "   ",
+            " VJ",
+            "VJ\t",
+            "V\nJ",
+            "V\u{0}J",
+            &"n".repeat(256),
+        ] {
+            assert!(validate_name(name).is_err(), "{name:?}");
+        }
+    }
+
+    #[test]
+    fn test_password_strength() {
+        let policy = PasswordPolicyConfig {
+            min_length: 10,
+            require_lowercase: true,
+            require_uppercase: true,
+            require_digit: true,
+            require_symbol: true,
+        };
+        for weak in ["123", "password12", "Password12", "Password!!"] {
+            assert!(matches!(
+                check_password_strength(weak, &policy),
+                Err(ApiError(ApiInnerError::WeakPassword(_)))
+            ));
+        }
+        assert!(check_password_strength("Password12!", &policy).is_ok());
+        assert!(check_password_strength(
+            "password",
+            &PasswordPolicyConfig::default()
+        )
+        .is_ok());
+    }
+
+    #[test]
+    fn test_validate_code() {
+        assert_eq!(validate_code("AB23CD").unwrap(), "AB23CD");
+        assert_eq!(validate_code(" ab23cd\n").unwrap(), "AB23CD");
+        // Wrong length, look-alikes the alphabet leaves out, non-ASCII.
+        for code in ["", "AB23C", "AB23CDE", "AB0OCD", "AB1ICD", "AB23Cé"] {
+            assert!(matches!(
+                validate_code(code),
+                Err(AuthError(AuthInnerError::WrongCode))
+            ));
+        }
+    }
+}
@@ -51,3 +51,19 @@ macro_rules! result_to_vec {
         }
     };
 }
+
+/// Implements `Debug` for a struct, printing the `masked` fields as `"***"`
+/// so that secrets don't end up in logs.
+#[macro_export]
+macro_rules! masked_debug {
+    ($type:ident { $($field:ident),* $(,)? } masked { $($secret:ident),* $(,)? }) => {
+        impl std::fmt::Debug for $type {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                f.debug_struct(stringify!($type))
+                    $(.field(stringify!($field), &self.$field))*
+                    $(.field(stringify!($secret), &"***"))*
+                    .finish()
+            }
+        }
+    };
+}
@@ -2,15 +2,18 @@ use serde::{Deserialize, Serialize};
 use sqlx::{types::chrono::NaiveDateTime, PgPool};
 
 use crate::{
-    library::error::InnerResult,
-    models::types::{AccountStatus, Language},
+    library::{
+        dber::{self, TableSchema},
+        error::{AppInnerError, InnerResult},
+    },
+    models::types::{AccountId, AccountStatus, Language},
 };
 
 #[allow(dead_code)]
 #[derive(sqlx::FromRow, Debug, Serialize, Deserialize, Clone)]
 #[sqlx(rename_all = "lowercase")]
 pub struct Account {
-    pub id: i64,
+    pub id: AccountId,
     pub name: String,
     pub email: String,
     pub password: String,
@@ -20,6 +23,12 @@ pub struct Account {
 
     pub created_at: NaiveDateTime,
     pub updated_at: Option<NaiveDateTime>,
+    /// Set once the account is soft-deleted, which hides it from lookups.
+    pub deleted_at: Option<NaiveDateTime>,
+    /// `None` until the first login.
+    pub last_login_at: Option<NaiveDateTime>,
+    /// In UTC, tokens issued before it are refused on refresh.
+    pub password_changed_at: Option<NaiveDateTime>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -30,7 +39,7 @@ pub struct LoginUserSchema {
 
 #[derive(Debug, Deserialize)]
 pub struct ResetPasswordSchema {
-    pub uid: i64,
+    pub uid: AccountId,
     pub password: String,
 }
 
@@ -42,98 +51,299 @@ pub struct RegisterSchema {
 }
 
 impl Account {
+    pub const SCHEMA: TableSchema = TableSchema {
+        table: "account",
+        columns: &[
+            ("id", "int8"),
+            ("name", "varchar"),
+            ("email", "varchar"),
+            ("password", "varchar"),
+            ("status", "account_status"),
+            ("language", "language"),
+            ("created_at", "timestamp"),
+            ("updated_at", "timestamp"),
+            ("deleted_at", "timestamp"),
+            ("last_login_at", "timestamp"),
+            ("password_changed_at", "timestamp"),
+        ],
+    };
+
+    /// Creates the account, or returns `None` when the email is taken in any
+    /// case. The unique index on `lower(email)` decides, so concurrent
+    /// registrations can't both succeed.
     pub async fn register_account(
         db: &PgPool,
         item: &RegisterSchema,
-    ) -> InnerResult<Self> {
-        let sql = r#"
-            INSERT INTO bw_account (name, email, password) VALUES ($1, $2, $3)
+    ) -> InnerResult<Option<Self>> {
+        let sql = dber::sql(
+            r#"
+            INSERT INTO {account} (name, email, password) VALUES ($1, $2, $3)
+            ON CONFLICT ((lower(email))) DO NOTHING
             RETURNING id,name,email,password,language,status,
-            created_at,updated_at,deleted_at
-            "#;
-        let map = sqlx::query_as(sql)
+            created_at,updated_at,deleted_at,last_login_at,password_changed_at
+            "#,
+        );
+        let map = sqlx::query_as(&sql)
             .bind(&item.name)
             .bind(&item.email)
             .bind(&item.password);
 
-        Ok(map.fetch_one(db).await?)
+        Ok(map.fetch_optional(db).await?)
     }
 
+    /// Counts soft-deleted accounts too, as their email is still taken.
+    /// Case-insensitive, matching accounts stored before emails were
+    /// normalized.
     pub async fn check_user_exists_by_email(
         db: &PgPool,
         email: &str,
     ) -> InnerResult<Option<bool>> {
-        let sql = r#"SELECT EXISTS(SELECT 1 FROM bw_account WHERE email = $1)"#;
-        let map = sqlx::query_scalar(sql).bind(email);
+        let sql = dber::sql(
+            r#"SELECT EXISTS(SELECT 1 FROM {account}
+            WHERE lower(email) = lower($1))"#,
+        );
+        let map = sqlx::query_scalar(&sql).bind(email);
         Ok(map.fetch_one(db).await?)
     }
 
+    /// Names identify accounts at login, so they're unique among the live
+    /// accounts.
+    pub async fn check_user_exists_by_name(
+        db: &PgPool,
+        name: &str,
+    ) -> InnerResult<Option<bool>> {
+        let sql = dber::sql(
+            r#"SELECT EXISTS(SELECT 1 FROM {account}
+            WHERE name = $1 AND deleted_at IS NULL)"#,
+        );
+        let map = sqlx::query_scalar(&sql).bind(name);
+        Ok(map.fetch_one(db).await?)
+    }
+
+    pub async fn count_accounts(db: &PgPool) -> InnerResult<i64> {
+        let sql = dber::sql(
+            r#"SELECT COUNT(*) FROM {account} WHERE deleted_at IS NULL"#,
+        );
+        let map = sqlx::query_scalar(&sql);
+        Ok(map.fetch_one(db).await?)
+    }
+
+    /// A page of the live accounts, oldest first.
+    pub async fn list_accounts(
+        db: &PgPool,
+        limit: i64,
+        offset: i64,
+    ) -> InnerResult<Vec<Self>> {
+        let sql = dber::sql(
+            r#"SELECT id,name,email,password,
+            language,status,
+            created_at,updated_at,deleted_at,last_login_at,password_changed_at
+            FROM {account} WHERE deleted_at IS NULL
+            ORDER BY created_at, id LIMIT $1 OFFSET $2"#,
+        );
+        let map = sqlx::query_as(&sql).bind(limit).bind(offset);
+        Ok(map.fetch_all(db).await?)
+    }
+
     pub async fn check_user_exists_by_uid(
         db: &PgPool,
-        uid: &i64,
+        uid: &AccountId,
     ) -> InnerResult<Option<bool>> {
-        let sql = r#"SELECT EXISTS(SELECT 1 FROM bw_account WHERE id = $1)"#;
-        let map = sqlx::query_scalar(sql).bind(uid);
+        let sql = dber::sql(
+            r#"SELECT EXISTS(SELECT 1 FROM {account} WHERE id = $1 AND deleted_at IS NULL)"#,
+        );
+        let map = sqlx::query_scalar(&sql).bind(uid);
         Ok(map.fetch_one(db).await?)
     }
 
+    /// The accounts named `name` or with the normalized `email`, matched
+    /// case-insensitively like in [`Account::fetch_user_by_email`].
     pub async fn fetch_user_by_email_or_name(
         db: &PgPool,
-        email_or_name: &str,
+        name: &str,
+        email: &str,
     ) -> InnerResult<Vec<Self>> {
-        let sql = r#"SELECT id,name,email,password,
+        let sql = dber::sql(
+            r#"SELECT id,name,email,password,
             language,status,
-            created_at,updated_at,deleted_at
-            FROM bw_account WHERE name = $1 or email = $1"#;
-        let map = sqlx::query_as(sql).bind(email_or_name);
+            created_at,updated_at,deleted_at,last_login_at,password_changed_at
+            FROM {account}
+            WHERE (name = $1 or lower(email) = $2) AND deleted_at IS NULL"#,
+        );
+        let map = sqlx::query_as(&sql).bind(name).bind(email);
         Ok(map.fetch_all(db).await?)
     }
 
     pub async fn fetch_user_by_uid(
         db: &PgPool,
-        uid: i64,
+        uid: AccountId,
     ) -> InnerResult<Option<Self>> {
-        let sql = r#"SELECT id,name,email,password,
+        let sql = dber::sql(
+            r#"SELECT id,name,email,password,
             language, status,
-            created_at,updated_at,deleted_at
-            FROM bw_account WHERE id = $1"#;
+            created_at,updated_at,deleted_at,last_login_at,password_changed_at
+            FROM {account} WHERE id = $1 AND deleted_at IS NULL"#,
+        );
 
-        let map = sqlx::query_as(sql).bind(uid);
+        let map = sqlx::query_as(&sql).bind(uid);
         Ok(map.fetch_optional(db).await?)
     }
 
+    /// Fetches the accounts of `uids` in no particular order, uids without
+    /// an account are simply omitted.
+    pub async fn fetch_users_by_uids(
+        db: &PgPool,
+        uids: &[AccountId],
+    ) -> InnerResult<Vec<Self>> {
+        let sql = dber::sql(
+            r#"SELECT id,name,email,password,
+            language, status,
+            created_at,updated_at,deleted_at,last_login_at,password_changed_at
+            FROM {account} WHERE id = ANY($1) AND deleted_at IS NULL"#,
+        );
+
+        let map = sqlx::query_as(&sql).bind(uids);
+        Ok(map.fetch_all(db).await?)
+    }
+
+    /// Emails are matched case-insensitively, like the unique index on
+    /// `lower(email)` compares them. More than one match, possible only
+    /// without that index, is reported as [`AppInnerError::DuplicateRows`]
+    /// rather than picking one.
     pub async fn fetch_user_by_email(
         db: &PgPool,
         email: &str,
     ) -> InnerResult<Option<Self>> {
-        let sql = r#"SELECT id,name,email,password,
+        let sql = dber::sql(
+            r#"SELECT id,name,email,password,
             language, status,
-            created_at,updated_at,deleted_at
-            FROM bw_account WHERE email = $1"#;
-        let map = sqlx::query_as(sql).bind(email);
-        Ok(map.fetch_optional(db).await?)
+            created_at,updated_at,deleted_at,last_login_at,password_changed_at
+            FROM {account}
+            WHERE lower(email) = lower($1) AND deleted_at IS NULL LIMIT 2"#,
+        );
+        let map = sqlx::query_as(&sql).bind(email);
+        let mut accounts: Vec<Self> = map.fetch_all(db).await?;
+        if accounts.len() > 1 {
+            tracing::error!(
+                "Multiple accounts match the email {}",
+                mask_email(email)
+            );
+            return Err(AppInnerError::DuplicateRows(Self::SCHEMA.table));
+        }
+        Ok(accounts.pop())
     }
 
     pub async fn update_password_by_uid(
         db: &PgPool,
         item: &ResetPasswordSchema,
     ) -> InnerResult<u64> {
-        let map =
-            sqlx::query(r#"UPDATE bw_account set password = $1 WHERE id = $2"#)
-                .bind(&item.password)
-                .bind(item.uid);
+        let sql = dber::sql(
+            r#"UPDATE {account}
+            SET password = $1, password_changed_at = now() AT TIME ZONE 'utc'
+            WHERE id = $2 AND deleted_at IS NULL"#,
+        );
+        let map = sqlx::query(&sql).bind(&item.password).bind(item.uid);
+        Ok(map.execute(db).await?.rows_affected())
+    }
+
+    /// Writes only the given fields, touching `updated_at` either way.
+    pub async fn update_profile_by_uid(
+        db: &PgPool,
+        uid: AccountId,
+        name: Option<String>,
+        language: Option<Language>,
+    ) -> InnerResult<u64> {
+        let mut query = sqlx::QueryBuilder::new(dber::sql(
+            "UPDATE {account} SET updated_at = now()",
+        ));
+        if let Some(name) = name {
+            query.push(", name = ").push_bind(name);
+        }
+        if let Some(language) = language {
+            query.push(", language = ").push_bind(language);
+        }
+        query
+            .push(" WHERE id = ")
+            .push_bind(uid)
+            .push(" AND deleted_at IS NULL");
+        Ok(query.build().execute(db).await?.rows_affected())
+    }
+
+    pub async fn touch_last_login(
+        db: &PgPool,
+        uid: AccountId,
+    ) -> InnerResult<u64> {
+        let sql = dber::sql(
+            r#"UPDATE {account} SET last_login_at = now()
+            WHERE id = $1 AND deleted_at IS NULL"#,
+        );
+        let map = sqlx::query(&sql).bind(uid);
         Ok(map.execute(db).await?.rows_affected())
     }
 
     pub async fn check_user_active_by_uid(
         db: &PgPool,
-        uid: i64,
+        uid: AccountId,
     ) -> InnerResult<Option<bool>> {
-        let map = sqlx::query_scalar(
-            "SELECT EXISTS(SELECT 1 FROM bw_account WHERE id = $1 and status = 'active')",
-        ).bind(uid);
+        let sql = dber::sql(
+            "SELECT EXISTS(SELECT 1 FROM {account} WHERE id = $1 and status = 'active' AND deleted_at IS NULL)",
+        );
+        let map = sqlx::query_scalar(&sql).bind(uid);
         Ok(map.fetch_one(db).await?)
     }
+
+    /// Activates an inactive account, returning it updated.
+    pub async fn activate_by_uid(
+        db: &PgPool,
+        uid: AccountId,
+    ) -> InnerResult<Option<Self>> {
+        let sql = dber::sql(
+            r#"
+            UPDATE {account} SET status = 'active'
+            WHERE id = $1 AND status = 'inactive' AND deleted_at IS NULL
+            RETURNING id,name,email,password,language,status,
+            created_at,updated_at,deleted_at,last_login_at,password_changed_at
+            "#,
+        );
+        let map = sqlx::query_as(&sql).bind(uid);
+        Ok(map.fetch_optional(db).await?)
+    }
+
+    /// Suspends the live accounts among `uids`, returning those found.
+    pub async fn suspend_by_uids(
+        db: &PgPool,
+        uids: &[AccountId],
+    ) -> InnerResult<Vec<AccountId>> {
+        let sql = dber::sql(
+            r#"UPDATE {account} SET status = 'suspended'
+            WHERE id = ANY($1) AND deleted_at IS NULL RETURNING id"#,
+        );
+        let ids: Vec<i64> = uids.iter().map(|uid| uid.0).collect();
+        let map = sqlx::query_scalar(&sql).bind(ids);
+        Ok(map.fetch_all(db).await?)
+    }
+
+    /// Marks the account deleted, returning whether it was live before.
+    pub async fn soft_delete_by_uid(
+        db: &PgPool,
+        uid: AccountId,
+    ) -> InnerResult<bool> {
+        let sql = dber::sql(
+            r#"UPDATE {account} SET deleted_at = now()
+            WHERE id = $1 AND deleted_at IS NULL"#,
+        );
+        let map = sqlx::query(&sql).bind(uid);
+        Ok(map.execute(db).await?.rows_affected() == 1)
+    }
+}
+
+/// Keeps the first two characters of the local part and the domain, e.g.
+/// `va*******@tuta.io`.
+fn mask_email(email: &str) -> String {
+    let (local, domain) = email.split_once('@').unwrap_or((email, ""));
+    let visible = local.chars().take(2).collect::<String>();
+    let hidden = "*".repeat(local.chars().count() - visible.chars().count());
+    format!("{visible}{hidden}@{domain}")
 }
 
 #[cfg(test)]
@@ -142,12 +352,12 @@ mod tests {
 
     use super::*;
 
-    const ACCOUNT_ID: i64 = 6192889942050345985;
+    const ACCOUNT_ID: AccountId = AccountId(6192889942050345985);
     const EMAIL: &str = "test@test.com";
     const MY_EMAIL: &str = "vainjoker@tuta.io";
     const NAME: &str = "Test User";
     const PASSWORD: &str = "password";
-    const NONEXISTENT_ACCOUNT_ID: i64 = 0;
+    const NONEXISTENT_ACCOUNT_ID: AccountId = AccountId(0);
     const NONEXISTENT_EMAIL: &str = "nonexistent@test.com";
 
     #[sqlx::test(fixtures(path = "../../fixtures", scripts("account")))]
@@ -158,7 +368,10 @@ mod tests {
             email: EMAIL.to_string(),
             password: PASSWORD.to_string(),
         };
-        let account = Account::register_account(&pool, &item).await.unwrap();
+        let account = Account::register_account(&pool, &item)
+            .await
+            .unwrap()
+            .unwrap();
         assert_eq!(account.email, EMAIL);
         assert_eq!(account.name, NAME);
 
@@ -175,6 +388,154 @@ mod tests {
         Ok(())
     }
 
+    #[sqlx::test(fixtures(path = "../../fixtures", scripts("account")))]
+    #[ignore]
+    async fn test_fetch_user_by_email_with_duplicates(
+        pool: PgPool,
+    ) -> sqlx::Result<()> {
+        sqlx::query("DROP INDEX idx_bw_account_email_lower")
+            .execute(&pool)
+            .await?;
+        sqlx::query(
+            "INSERT INTO bw_account (name, email, password) VALUES ($1, $2, $3)",
+        )
+        .bind(NAME)
+        .bind(MY_EMAIL.to_uppercase())
+        .bind(PASSWORD)
+        .execute(&pool)
+        .await?;
+        let result = Account::fetch_user_by_email(&pool, MY_EMAIL).await;
+        assert!(matches!(
+            result,
+            Err(AppInnerError::DuplicateRows("account"))
+        ));
+
+        Ok(())
+    }
+
+    #[sqlx::test(fixtures(path = "../../fixtures", scripts("account")))]
+    #[ignore]
+    async fn test_prefixed_tables(pool: PgPool) -> sqlx::Result<()> {
+        sqlx::query("ALTER TABLE bw_account RENAME TO t1_account")
+            .execute(&pool)
+            .await?;
+        let (account, count) = dber::with_table_prefix("t1_".into(), async {
+            (
+                Account::fetch_user_by_uid(&pool, ACCOUNT_ID).await.unwrap(),
+                Account::count_accounts(&pool).await.unwrap(),
+            )
+        })
+        .await
+        .unwrap();
+        assert_eq!(account.unwrap().email, MY_EMAIL);
+        assert_eq!(count, 1);
+        // The default prefix no longer finds the table.
+        assert!(Account::count_accounts(&pool).await.is_err());
+
+        Ok(())
+    }
+
+    #[sqlx::test(fixtures(path = "../../fixtures", scripts("account")))]
+    #[ignore]
+    async fn test_soft_delete_by_uid(pool: PgPool) -> sqlx::Result<()> {
+        assert!(Account::soft_delete_by_uid(&pool, ACCOUNT_ID)
+            .await
+            .unwrap());
+        // Already deleted.
+        assert!(!Account::soft_delete_by_uid(&pool, ACCOUNT_ID)
+            .await
+            .unwrap());
+
+        assert!(Account::fetch_user_by_uid(&pool, ACCOUNT_ID)
+            .await
+            .unwrap()
+            .is_none());
+        assert!(Account::fetch_user_by_email(&pool, MY_EMAIL)
+            .await
+            .unwrap()
+            .is_none());
+        assert!(Account::fetch_user_by_email_or_name(&pool, "VJ", MY_EMAIL)
+            .await
+            .unwrap()
+            .is_empty());
+        assert!(!Account::check_user_exists_by_uid(&pool, &ACCOUNT_ID)
+            .await
+            .unwrap()
+            .unwrap());
+        assert_eq!(Account::count_accounts(&pool).await.unwrap(), 0);
+        // The email stays taken.
+        assert!(Account::check_user_exists_by_email(&pool, MY_EMAIL)
+            .await
+            .unwrap()
+            .unwrap());
+
+        Ok(())
+    }
+
+    #[sqlx::test(fixtures(path = "../../fixtures", scripts("account")))]
+    #[ignore]
+    async fn test_unknown_enum_labels_decoded(
+        pool: PgPool,
+    ) -> sqlx::Result<()> {
+        // As left behind by a rolled back migration.
+        sqlx::query("ALTER TYPE language ADD VALUE 'de-DE'")
+            .execute(&pool)
+            .await?;
+        sqlx::query("ALTER TYPE account_status ADD VALUE 'banned'")
+            .execute(&pool)
+            .await?;
+        let sql = dber::sql(
+            "UPDATE {account} SET language = 'de-DE', status = 'banned' WHERE id = $1",
+        );
+        sqlx::query(&sql).bind(ACCOUNT_ID).execute(&pool).await?;
+
+        let accounts = Account::list_accounts(&pool, 10, 0).await.unwrap();
+        let account = accounts.iter().find(|a| a.id == ACCOUNT_ID).unwrap();
+        assert_eq!(account.language, Language::EnUs);
+        assert_eq!(account.status, AccountStatus::Suspend);
+
+        Ok(())
+    }
+
+    #[sqlx::test(fixtures(path = "../../fixtures", scripts("account")))]
+    #[ignore]
+    async fn test_list_accounts(pool: PgPool) -> sqlx::Result<()> {
+        for i in 0..4 {
+            let item = RegisterSchema {
+                name: format!("{NAME} {i}"),
+                email: format!("test{i}@test.com"),
+                password: PASSWORD.to_string(),
+            };
+            Account::register_account(&pool, &item).await.unwrap();
+        }
+        Account::soft_delete_by_uid(&pool, ACCOUNT_ID)
+            .await
+            .unwrap();
+        assert_eq!(Account::count_accounts(&pool).await.unwrap(), 4);
+
+        let mut names = Vec::new();
+        for offset in [0, 3] {
+            let page = Account::list_accounts(&pool, 3, offset).await.unwrap();
+            names.extend(page.into_iter().map(|account| account.name));
+        }
+        let expected =
+            (0..4).map(|i| format!("{NAME} {i}")).collect::<Vec<_>>();
+        assert_eq!(names, expected);
+        assert!(Account::list_accounts(&pool, 3, 6)
+            .await
+            .unwrap()
+            .is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_mask_email() {
+        assert_eq!(mask_email(MY_EMAIL), "va*******@tuta.io");
+        assert_eq!(mask_email("v@tuta.io"), "v@tuta.io");
+        assert_eq!(mask_email("invalid"), "in*****@");
+    }
+
     #[sqlx::test(fixtures(path = "../../fixtures", scripts("account")))]
     #[ignore]
     async fn test_fetch_user_by_uid(pool: PgPool) -> sqlx::Result<()> {
@@ -185,6 +546,57 @@ mod tests {
         Ok(())
     }
 
+    #[sqlx::test(fixtures(path = "../../fixtures", scripts("account")))]
+    #[ignore]
+    async fn test_fetch_users_by_uids(pool: PgPool) -> sqlx::Result<()> {
+        let accounts = Account::fetch_users_by_uids(
+            &pool,
+            &[ACCOUNT_ID, NONEXISTENT_ACCOUNT_ID],
+        )
+        .await
+        .unwrap();
+        assert_eq!(accounts.len(), 1);
+        assert_eq!(accounts[0].id, ACCOUNT_ID);
+
+        let accounts = Account::fetch_users_by_uids(&pool, &[]).await.unwrap();
+        assert!(accounts.is_empty());
+
+        Ok(())
+    }
+
+    #[sqlx::test(fixtures(path = "../../fixtures", scripts("account")))]
+    #[ignore]
+    async fn test_fetch_user_by_uid_after_email_change(
+        pool: PgPool,
+    ) -> sqlx::Result<()> {
+        sqlx::query("UPDATE bw_account SET email = $1 WHERE id = $2")
+            .bind(EMAIL)
+            .bind(ACCOUNT_ID)
+            .execute(&pool)
+            .await?;
+        let account =
+            Account::fetch_user_by_uid(&pool, ACCOUNT_ID).await.unwrap();
+        assert_eq!(account.unwrap().email, EMAIL);
+
+        Ok(())
+    }
+
+    #[sqlx::test(fixtures(path = "../../fixtures", scripts("account")))]
+    #[ignore]
+    async fn test_fetch_user_by_email_or_name(
+        pool: PgPool,
+    ) -> sqlx::Result<()> {
+        let fetch = |name, email| {
+            Account::fetch_user_by_email_or_name(&pool, name, email)
+        };
+        assert_eq!(fetch("VJ", "vj").await.unwrap().len(), 1);
+        assert_eq!(fetch(MY_EMAIL, MY_EMAIL).await.unwrap().len(), 1);
+        // Names are matched as typed.
+        assert!(fetch("vj", "vj").await.unwrap().is_empty());
+
+        Ok(())
+    }
+
     #[sqlx::test(fixtures(path = "../../fixtures", scripts("account")))]
     #[ignore]
     async fn test_check_user_exists_by_email(pool: PgPool) -> sqlx::Result<()> {
@@ -196,6 +608,29 @@ mod tests {
         Ok(())
     }
 
+    #[sqlx::test(fixtures(path = "../../fixtures", scripts("account")))]
+    #[ignore]
+    async fn test_check_user_exists_by_name(pool: PgPool) -> sqlx::Result<()> {
+        let exists = |name| Account::check_user_exists_by_name(&pool, name);
+        assert!(exists("VJ").await.unwrap().unwrap());
+        assert!(!exists(NAME).await.unwrap().unwrap());
+
+        Account::soft_delete_by_uid(&pool, ACCOUNT_ID)
+            .await
+            .unwrap();
+        assert!(!exists("VJ").await.unwrap().unwrap());
+
+        Ok(())
+    }
+
+    #[sqlx::test(fixtures(path = "../../fixtures", scripts("account")))]
+    #[ignore]
+    async fn test_count_accounts(pool: PgPool) -> sqlx::Result<()> {
+        assert_eq!(Account::count_accounts(&pool).await.unwrap(), 1);
+
+        Ok(())
+    }
+
     #[sqlx::test(fixtures(path = "../../fixtures", scripts("account")))]
     #[ignore]
     async fn test_check_user_exists_by_uid(pool: PgPool) -> sqlx::Result<()> {
@@ -229,6 +664,115 @@ mod tests {
             Account::update_password_by_uid(&pool, &item).await.unwrap();
         assert_eq!(rows_affected, 1);
 
+        let user = Account::fetch_user_by_uid(&pool, ACCOUNT_ID)
+            .await
+            .unwrap()
+            .unwrap();
+        let changed_at = user.password_changed_at.unwrap().and_utc();
+        assert!((chrono::Utc::now() - changed_at).num_seconds().abs() < 60);
+
+        Ok(())
+    }
+
+    #[sqlx::test(fixtures(path = "../../fixtures", scripts("account")))]
+    #[ignore]
+    async fn test_activate_by_uid(pool: PgPool) -> sqlx::Result<()> {
+        let user = Account::activate_by_uid(&pool, ACCOUNT_ID)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(user.status, AccountStatus::Active);
+        // Only inactive accounts are activated.
+        assert!(Account::activate_by_uid(&pool, ACCOUNT_ID)
+            .await
+            .unwrap()
+            .is_none());
+
+        Ok(())
+    }
+
+    #[sqlx::test(fixtures(path = "../../fixtures", scripts("account")))]
+    #[ignore]
+    async fn test_suspend_by_uids(pool: PgPool) -> sqlx::Result<()> {
+        let suspended =
+            Account::suspend_by_uids(&pool, &[ACCOUNT_ID, AccountId(1)])
+                .await
+                .unwrap();
+        assert_eq!(suspended, vec![ACCOUNT_ID]);
+        let user = Account::fetch_user_by_uid(&pool, ACCOUNT_ID)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(user.status, AccountStatus::Suspend);
+
+        Ok(())
+    }
+
+    #[sqlx::test(fixtures(path = "../../fixtures", scripts("account")))]
+    #[ignore]
+    async fn test_touch_last_login(pool: PgPool) -> sqlx::Result<()> {
+        let last_login = || async {
+            Account::fetch_user_by_uid(&pool, ACCOUNT_ID)
+                .await
+                .unwrap()
+                .unwrap()
+                .last_login_at
+        };
+        assert_eq!(last_login().await, None);
+
+        assert_eq!(
+            Account::touch_last_login(&pool, ACCOUNT_ID).await.unwrap(),
+            1
+        );
+        let first = last_login().await.unwrap();
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        Account::touch_last_login(&pool, ACCOUNT_ID).await.unwrap();
+        assert!(last_login().await.unwrap() > first);
+
+        Ok(())
+    }
+
+    #[sqlx::test(fixtures(path = "../../fixtures", scripts("account")))]
+    #[ignore]
+    async fn test_update_profile_by_uid(pool: PgPool) -> sqlx::Result<()> {
+        let before = Account::fetch_user_by_uid(&pool, ACCOUNT_ID)
+            .await
+            .unwrap()
+            .unwrap();
+        let update = |name: Option<&str>, language| {
+            Account::update_profile_by_uid(
+                &pool,
+                ACCOUNT_ID,
+                name.map(str::to_string),
+                language,
+            )
+        };
+        let fetch = || async {
+            Account::fetch_user_by_uid(&pool, ACCOUNT_ID)
+                .await
+                .unwrap()
+                .unwrap()
+        };
+
+        assert_eq!(update(None, Some(Language::FrFr)).await.unwrap(), 1);
+        let user = fetch().await;
+        assert_eq!(user.language, Language::FrFr);
+        assert_eq!(user.name, before.name);
+
+        assert_eq!(update(Some("Renamed"), None).await.unwrap(), 1);
+        let user = fetch().await;
+        assert_eq!(user.name, "Renamed");
+        assert_eq!(user.language, Language::FrFr);
+
+        assert_eq!(
+            update(Some("Both"), Some(Language::ZhCn)).await.unwrap(),
+            1
+        );
+        let user = fetch().await;
+        assert_eq!(user.name, "Both");
+        assert_eq!(user.language, Language::ZhCn);
+        assert_eq!(user.email, before.email);
+
         Ok(())
     }
 
@@ -242,8 +786,30 @@ mod tests {
             email: MY_EMAIL.to_string(),
             password: "password".to_string(),
         };
-        let result = Account::register_account(&pool, &item).await;
-        assert!(result.is_err());
+        let result = Account::register_account(&pool, &item).await.unwrap();
+        assert!(result.is_none());
+
+        Ok(())
+    }
+
+    #[sqlx::test(fixtures(path = "../../fixtures", scripts("account")))]
+    #[ignore]
+    async fn test_register_account_with_case_variant_email(
+        pool: PgPool,
+    ) -> sqlx::Result<()> {
+        // Stored as typed, like accounts registered before normalizing.
+        sqlx::query("UPDATE bw_account SET email = $1 WHERE id = $2")
+            .bind(MY_EMAIL.to_uppercase())
+            .bind(ACCOUNT_ID)
+            .execute(&pool)
+            .await?;
+        let item = RegisterSchema {
+            name: "New User".to_string(),
+            email: MY_EMAIL.to_string(),
+            password: "password".to_string(),
+        };
+        let result = Account::register_account(&pool, &item).await.unwrap();
+        assert!(result.is_none());
 
         Ok(())
     }
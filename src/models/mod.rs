@@ -1,2 +1,7 @@
 pub mod account;
 pub mod types;
+
+use crate::library::dber::TableSchema;
+
+/// Tables checked by the startup schema self-check.
+pub const SCHEMA: &[TableSchema] = &[account::Account::SCHEMA];
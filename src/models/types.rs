@@ -1,34 +1,220 @@
+use std::fmt;
+
 use serde::{Deserialize, Serialize};
+use sqlx::{
+    encode::IsNull,
+    error::BoxDynError,
+    postgres::{PgArgumentBuffer, PgTypeInfo, PgValueRef},
+    Decode, Encode, Postgres, Type,
+};
 
-#[derive(sqlx::Type, Debug, Clone, Copy, Serialize, Deserialize)]
-#[sqlx(type_name = "language")]
-pub enum Language {
-    #[sqlx(rename = "en-US")]
-    EnUs,
-    #[sqlx(rename = "zh-CN")]
-    ZhCn,
-    #[sqlx(rename = "fr-FR")]
-    FrFr,
-    #[sqlx(rename = "es-ES")]
-    EsEs,
+use crate::library::cfg;
+
+/// Maps `$enum` to the Postgres enum `$type_name` by label. A label the enum
+/// doesn't know, e.g. after a migration rollback, decodes to `$fallback`
+/// with a warning rather than failing the whole query, unless
+/// `app.strict_enum_decoding` is set.
+macro_rules! tolerant_pg_enum {
+    (
+        $enum:ident, $type_name:literal, fallback = $fallback:expr,
+        { $($variant:ident => $label:literal),+ $(,)? }
+    ) => {
+        impl $enum {
+            /// The label in the database.
+            pub const fn db_label(self) -> &'static str {
+                match self {
+                    $(Self::$variant => $label,)+
+                }
+            }
+
+            fn from_db_label(label: &str, strict: bool) -> Result<Self, BoxDynError> {
+                match label {
+                    $($label => Ok(Self::$variant),)+
+                    _ if strict => Err(format!(
+                        "unknown {} label `{label}`", $type_name
+                    ).into()),
+                    _ => {
+                        tracing::warn!(
+                            "Unknown {} label `{label}`, decoded as {:?}",
+                            $type_name,
+                            $fallback
+                        );
+                        Ok($fallback)
+                    }
+                }
+            }
+        }
+
+        impl Type<Postgres> for $enum {
+            fn type_info() -> PgTypeInfo {
+                PgTypeInfo::with_name($type_name)
+            }
+        }
+
+        impl Encode<'_, Postgres> for $enum {
+            fn encode_by_ref(&self, buf: &mut PgArgumentBuffer) -> IsNull {
+                <&str as Encode<Postgres>>::encode(self.db_label(), buf)
+            }
+        }
+
+        impl<'r> Decode<'r, Postgres> for $enum {
+            fn decode(value: PgValueRef<'r>) -> Result<Self, BoxDynError> {
+                let label = <&str as Decode<Postgres>>::decode(value)?;
+                let strict = cfg::try_config()
+                    .is_some_and(|cfg| cfg.app.strict_enum_decoding);
+                Self::from_db_label(label, strict)
+            }
+        }
+    };
 }
 
+/// The id of an account, kept apart from other integers so it can't be
+/// mixed up with them.
 #[derive(
     sqlx::Type,
     Debug,
     Clone,
     Copy,
+    PartialEq,
+    Eq,
+    Hash,
+    PartialOrd,
+    Ord,
     Serialize,
     Deserialize,
-    PartialOrd,
-    PartialEq,
 )]
-#[sqlx(type_name = "account_status")]
+#[sqlx(transparent)]
+#[serde(transparent)]
+pub struct AccountId(pub i64);
+
+impl fmt::Display for AccountId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl From<i64> for AccountId {
+    fn from(id: i64) -> Self {
+        Self(id)
+    }
+}
+
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize,
+)]
+pub enum Language {
+    #[default]
+    EnUs,
+    ZhCn,
+    FrFr,
+    EsEs,
+}
+
+tolerant_pg_enum!(Language, "language", fallback = Language::EnUs, {
+    EnUs => "en-US",
+    ZhCn => "zh-CN",
+    FrFr => "fr-FR",
+    EsEs => "es-ES",
+});
+
+impl Language {
+    /// The BCP 47 tag, as stored in the database.
+    pub const fn tag(self) -> &'static str {
+        self.db_label()
+    }
+
+    /// The data residency zone of users of the language, which audit records
+    /// are tagged with. Overridable per tag through `app.audit.regions`.
+    pub const fn region(self) -> &'static str {
+        match self {
+            Self::EnUs => "us",
+            Self::ZhCn => "cn",
+            Self::FrFr | Self::EsEs => "eu",
+        }
+    }
+
+    /// Resolves a BCP 47 language tag, falling back from the full tag to its
+    /// primary language (`fr-CA` → `fr` → [`Language::FrFr`]).
+    pub fn from_tag(tag: &str) -> Option<Self> {
+        let tag = tag.trim();
+        let exact = match tag.to_ascii_lowercase().as_str() {
+            "en-us" => Some(Self::EnUs),
+            "zh-cn" => Some(Self::ZhCn),
+            "fr-fr" => Some(Self::FrFr),
+            "es-es" => Some(Self::EsEs),
+            _ => None,
+        };
+        exact.or_else(|| {
+            let primary = tag.split('-').next()?.to_ascii_lowercase();
+            match primary.as_str() {
+                "en" => Some(Self::EnUs),
+                "zh" => Some(Self::ZhCn),
+                "fr" => Some(Self::FrFr),
+                "es" => Some(Self::EsEs),
+                _ => None,
+            }
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialOrd, PartialEq)]
 pub enum AccountStatus {
-    #[sqlx(rename = "active")]
     Active,
-    #[sqlx(rename = "inactive")]
     Inactive,
-    #[sqlx(rename = "suspended")]
     Suspend,
 }
+
+// Unknown statuses grant nothing.
+tolerant_pg_enum!(AccountStatus, "account_status", fallback = AccountStatus::Suspend, {
+    Active => "active",
+    Inactive => "inactive",
+    Suspend => "suspended",
+});
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_account_id_round_trip() {
+        let id = AccountId::from(6_192_889_942_050_345_985);
+        assert_eq!(id, AccountId(6_192_889_942_050_345_985));
+        assert_eq!(id.to_string(), "6192889942050345985");
+
+        let json = serde_json::to_string(&id).unwrap();
+        assert_eq!(json, "6192889942050345985");
+        assert_eq!(serde_json::from_str::<AccountId>(&json).unwrap(), id);
+    }
+
+    #[test]
+    fn test_language_region() {
+        for (language, region) in [
+            (Language::EnUs, "us"),
+            (Language::ZhCn, "cn"),
+            (Language::FrFr, "eu"),
+            (Language::EsEs, "eu"),
+        ] {
+            assert_eq!(language.region(), region);
+            assert_eq!(Language::from_tag(language.tag()), Some(language));
+        }
+    }
+
+    #[test]
+    fn test_unknown_db_labels() {
+        assert_eq!(
+            Language::from_db_label("zh-CN", true).unwrap(),
+            Language::ZhCn
+        );
+        assert_eq!(
+            Language::from_db_label("de-DE", false).unwrap(),
+            Language::EnUs
+        );
+        assert!(Language::from_db_label("de-DE", true).is_err());
+
+        assert_eq!(
+            AccountStatus::from_db_label("banned", false).unwrap(),
+            AccountStatus::Suspend
+        );
+        assert!(AccountStatus::from_db_label("banned", true).is_err());
+    }
+}